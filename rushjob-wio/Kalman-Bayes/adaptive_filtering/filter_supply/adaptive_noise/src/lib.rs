@@ -0,0 +1,154 @@
+#![no_std]
+// 適応ノイズ推定(noise_adjust_fn用の関数集)
+//
+// FilterBaseのnoise_adjust_fnに設定して使う。
+// fb.ns_count = Some(0)で有効になり、
+// 更新毎にNoiseContextを渡して呼出される。
+//
+// # eps: noise_adjust_eps
+//    eps > Q_adjust_param なら
+//      Q *= Q_scale_factor, ns_count += 1
+//    そうでなければ元に戻す
+//
+// # std: noise_adjust_std
+//    |y[i]| > Q_adjust_param * √S[i][i] なら
+//      phi += Q_scale_factor, ns_count += 1
+//    そうでなければ元に戻す
+//    Q = Q * phi(新) / phi(旧)
+//    ※fb.phiを設定し、Qはphiに比例
+//      (DiscreteWhiteNoise::noise_block(dt, phi)
+//       等)していること
+//
+// # 観測残差窓: noise_adjust_window
+//    C = 直近window件の y @ y.T の平均
+//    R = C - H @ P- @ H.T = C - (S - R)
+//    Q = K @ C @ K.T
+//    window件たまるまでは調整しない
+//
+// # Sage-Husa: noise_adjust_sage_husa
+//    d = (1 - b) / (1 - b^(k+1)) b:忘却係数
+//    R = (1-d)R + d(y @ y.T - H @ P- @ H.T)
+//      = R + d(y @ y.T - S)
+//    Q = (1-d)Q + d(K @ y @ y.T @ K.T
+//                   + P - F @ P(前回) @ F.T)
+//      = Q + d K @ (y @ y.T - S) @ K.T
+//
+//  窓、Sage-HusaのRが正定値でなくなる場合、
+//  Qの対角に負値が出る場合はその回の推定を
+//  反映しない。
+//  推定するQ,Rはfb.noiseのadapt_q, adapt_rで
+//  選択する(窓長window, 忘却係数forgetも)。
+//
+// 使い方
+//   kf.fb.ns_count = Some(0);
+//   kf.fb.noise.forget = 0.98;
+//   kf.fb.noise_adjust_fn =
+//     noise_adjust_sage_husa;
+// -----------------------------------------
+use filter_base::*;
+pub use filter_base::noise_adjust_fn_default
+    as noise_adjust_eps;
+use nalgebra::SMatrix;
+
+// 観測残差の標準偏差でのﾌﾟﾛｾｽﾉｲｽﾞ調整
+pub fn noise_adjust_std
+       <const M: usize, const N: usize>
+(
+  c: &mut NoiseContext<M, N>,
+)
+{
+  let phi = *c.eps;
+  let over = (0..N).any(|i|
+               c.y[i].abs() >
+               c.Q_adjust_param *
+               libm_sqrt(c.S[(i, i)])
+             );
+  if over {
+    *c.eps += c.Q_scale_factor;
+    *c.ns_count += 1;
+  } else if *c.ns_count > 0 {
+    *c.eps -= c.Q_scale_factor;
+    *c.ns_count -= 1;
+  }
+  // Qはphiに比例
+  if phi != 0.0 {
+    *c.Q *= *c.eps / phi;
+  }
+}
+// 観測残差窓によるノイズ推定
+#[allow(non_snake_case)]
+pub fn noise_adjust_window
+       <const M: usize, const N: usize>
+(
+  c: &mut NoiseContext<M, N>,
+)
+{
+  c.state.push(c.y);
+  let Some(C) = c.state.innovation_cov() else {
+    return;
+  };
+  if c.state.adapt_r {
+    // R = C - (S - R)
+    let R = C - (c.S - *c.R);
+    r_set(c.R, R);
+  }
+  if c.state.adapt_q {
+    // Q = K @ C @ K.T
+    let Q = c.K * C * c.K.transpose();
+    q_set(c.Q, Q);
+  }
+}
+// Sage-Husaによるノイズ推定
+#[allow(non_snake_case)]
+pub fn noise_adjust_sage_husa
+       <const M: usize, const N: usize>
+(
+  c: &mut NoiseContext<M, N>,
+)
+{
+  let b = c.state.forget;
+  let k = c.state.count.min(1000) as i32;
+  let d = (1.0 - b) /
+          (1.0 - <f32 as nalgebra::ComplexField>
+                   ::powi(b, k + 1));
+  c.state.count += 1;
+  // y @ y.T - S
+  let D = *c.y * c.y.transpose() - *c.S;
+  if c.state.adapt_r {
+    let R = *c.R + D * d;
+    r_set(c.R, R);
+  }
+  if c.state.adapt_q {
+    let Q = *c.Q + c.K * D * c.K.transpose() * d;
+    q_set(c.Q, Q);
+  }
+}
+// Rの設定(対称化、正定値の場合のみ)
+#[allow(non_snake_case)]
+fn r_set<const N: usize>(
+  R : &mut SMatrix<f32, N, N>,
+  Rn: SMatrix<f32, N, N>,
+) {
+  let Rn = (Rn + Rn.transpose()) * 0.5;
+  if Rn.iter().all(|v| v.is_finite()) &&
+     Rn.cholesky().is_some() {
+    *R = Rn;
+  }
+}
+// Qの設定(対称化、対角が非負の場合のみ)
+// ※離散化したQは特異なことが多いので
+//   正定値は要求しない
+#[allow(non_snake_case)]
+fn q_set<const M: usize>(
+  Q : &mut SMatrix<f32, M, M>,
+  Qn: SMatrix<f32, M, M>,
+) {
+  let Qn = (Qn + Qn.transpose()) * 0.5;
+  if Qn.iter().all(|v| v.is_finite()) &&
+     Qn.diagonal().iter().all(|v| *v >= 0.0) {
+    *Q = Qn;
+  }
+}
+fn libm_sqrt(v: f32) -> f32 {
+  <f32 as nalgebra::ComplexField>::sqrt(v)
+}
@@ -1,5 +1,9 @@
 #![no_std]
 use filter_base::*;
+// ﾌﾟﾛｾｽﾉｲｽﾞ調整関数
+use adaptive_noise::noise_adjust_std;
+// Filterトレイト(利用側でのメソッド呼出用)
+pub use filter_base::Filter;
 use mv_kalmanfilter::*;
 use discrete_white_noise::*;
 use nalgebra::SMatrix;
@@ -15,20 +19,19 @@ pub const FLC: usize = 6;
 type Kf1 = KalmanFilter<2, 1, 1>;
 type Kf2 = KalmanFilter<3, 1, 1>;
 type Kf3 = UsKalmanFilter<4, 2, 1, 9, 1, 1>;
+#[allow(clippy::large_enum_variant)]
 pub enum Filters {
   Kf1(Kf1), 
   Kf2(Kf2),
   Kf3(Kf3),
 }
-#[allow(non_snake_case)]
-impl Filters {
-  // filter_supply_implマクロによる実装
-  filter_supply_impl!(
-    Kf1,
-    Kf2,
-    Kf3,
-  );
-}
+// filter_supply_implマクロによるFilter実装
+filter_supply_impl!(
+  Filters:
+  Kf1,
+  Kf2,
+  Kf3,
+);
 // 供給フィルタの格納 ====================
 pub fn filter_supply() -> [Filters; FLC] { 
   [ca_filter(),
@@ -69,39 +72,9 @@ fn ca_filter_noise_std() -> Filters {
   // std_scale設定
   kf.fb.Q_adjust_param = 2.;
   // ﾌﾟﾛｾｽﾉｲｽﾞ調整関数入替え
-  kf.fb.noise_adjust_fn = noise_adjast_std;
+  kf.fb.noise_adjust_fn = noise_adjust_std;
   Filters::Kf2(kf)
 }
-#[allow(non_snake_case)]
-fn noise_adjast_std
-   <const M: usize, const N: usize>
-(
-  Q: &mut SMatrix<f32, M, M>, // Q
-  y:     &SMatrix<f32, N, 1>, // y
-  S:     &SMatrix<f32, N, N>, // S
-  phi     : &mut f32,         // phi
-  ns_count: &mut usize,       // ns_count
-  Q_scale_factor: f32,
-  std_scale: f32,
-)
-{  
-  const B: usize = 3; // ﾌﾟﾛｾｽﾉｲｽﾞﾌﾞﾛｯｸ
-  let std = S[(0, 0)].sqrt();
-  let dt = 1.0;
-  if y[(0, 0)].abs() > std_scale*std {
-    *phi += Q_scale_factor;
-    *ns_count += 1;
-  } else if *ns_count > 0 {
-    *phi -= Q_scale_factor;
-    *ns_count -= 1;
-  }
-  // ノイズ設定
-    // ノイズブロック作成
-  let bn: SMatrix<f32, B, B> =  
-    DiscreteWhiteNoise::noise_block(dt, *phi);
-    // プロセスノイズ設定
-  Q.copy_from_slice(bn.as_slice());
-}
 // 加速度フィルタ ==========================
 fn mv_filter1() -> Kf2 {
 // シミュレーション設定
@@ -128,7 +101,7 @@ fn mv_filter1() -> Kf2 {
     [p, 1.0, 1.0].into_iter()
   );
   // 累積尤度計算設定
-  kf.fb.cum_lh = Some(0.4);
+  kf.fb.cum_lh_set(0.4);
   // ノイズ設定
     // ノイズブロック作成
   let bn: SMatrix<f32, B, B> =  
@@ -164,7 +137,7 @@ fn cv_filter() -> Filters {
     // プロセスノイズ設定
   kf.fb.Q *= q;
   // 累積尤度計算設定
-  kf.fb.cum_lh = Some(0.2);
+  kf.fb.cum_lh_set(0.2);
 
   Filters::Kf1(kf)
 }
@@ -214,12 +187,9 @@ fn us_filter() -> Filters {
   let bn: SMatrix<f32, B, B> =  
     DiscreteWhiteNoise::noise_block(dt, q*q); 
   // プロセスノイズ設定
-  (0..M/B).for_each(|i| 
-    ukf.fb.Q.view_mut((i*B, i*B), (B, B))
-       .copy_from(&bn)
-  );
+  ukf.fb.Q = block_diag::<M, B>(&bn);
   // 累積尤度計算設定
-  ukf.fb.cum_lh = Some(0.4);
+  ukf.fb.cum_lh_set(0.4);
 
   Filters::Kf3(ukf)
 }
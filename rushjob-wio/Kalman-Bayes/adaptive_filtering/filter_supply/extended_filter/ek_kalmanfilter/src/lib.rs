@@ -0,0 +1,375 @@
+#![no_std]
+// # 予測
+//    F = ∂fx/∂x (x)       状態遷移関数のﾔｺﾋﾞｱﾝ
+//    x = fx(x, u, dt)
+//    P = F @ P @ F.T + Q
+//
+// # 更新
+//    H = ∂hx/∂x (x)       観測関数のﾔｺﾋﾞｱﾝ
+//    y = z - hx(x)
+//    S = H @ P @ H.T + R
+//    K = P @ H.T @ inv(S)
+//    x += K @ y
+//    P = (I-KH)P(I-KH)' + KRK'
+//
+//状態
+//  x: 状態の平均         m x 1
+//  P: 状態の共分散行列   m x m
+//プロセスモデル
+//  fx: 状態遷移関数     (m x 1, c x 1, f32)
+//                       -> m x 1
+//  Fj: fxのﾔｺﾋﾞｱﾝ        m x m
+//  Q: プロセスモデルに加わるノイズの
+//     共分散行列         m x m
+//観測値
+//  z: 観測値平均         n x 1
+//  R: 観測値に加わるノイズの
+//           共分散行列   n x n
+//観測関数
+//  hx: 観測関数         (m x 1) -> n x 1
+//      状態 x を観測値 z に変換
+//  Hj: hxのﾔｺﾋﾞｱﾝ        n x m
+//
+// ﾔｺﾋﾞｱﾝ関数(jacobian_fx, jacobian_hx)を
+// 設定しない場合(None)は中心差分による
+// 数値ﾔｺﾋﾞｱﾝで代用する。
+// fx, hxのデフォルトは線形(F @ x + B @ u,
+// H @ x)なので、何も設定しなければ
+// KalmanFilterと同じ動きになる。
+// -----------------------------------------
+use nalgebra::{SMatrix, DimMin, Const};
+use filter_base::*;
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+// 状態遷移関数(fx)定義型
+pub type FxFn<const M: usize, const C: usize> =
+  fn(&SMatrix<f32, M, 1>,   // x
+     &SMatrix<f32, C, 1>,   // u
+     &SMatrix<f32, M, M>,   // F
+     &SMatrix<f32, M, C>,   // B
+     f32,                   // dt
+  ) -> SMatrix<f32, M, 1>;
+// 状態遷移関数ﾔｺﾋﾞｱﾝ定義型
+pub type JacobianFxFn
+  <const M: usize, const C: usize> =
+  fn(&SMatrix<f32, M, 1>,   // x
+     &SMatrix<f32, C, 1>,   // u
+     &SMatrix<f32, M, M>,   // F
+     &SMatrix<f32, M, C>,   // B
+     f32,                   // dt
+  ) -> SMatrix<f32, M, M>;
+// 観測関数(hx)定義型
+pub type HxFn<const M:  usize,
+              const N:  usize,
+              const LR: usize,
+              const LC: usize> =
+  fn(&SMatrix<f32, M, 1>,   // x
+     &SMatrix<f32, N, M>,   // H
+     &SMatrix<f32, LR, LC>, // lm
+  ) -> SMatrix<f32, N, 1>;
+// 観測関数ﾔｺﾋﾞｱﾝ定義型
+pub type JacobianHxFn<const M:  usize,
+                      const N:  usize,
+                      const LR: usize,
+                      const LC: usize> =
+  fn(&SMatrix<f32, M, 1>,   // x
+     &SMatrix<f32, N, M>,   // H
+     &SMatrix<f32, LR, LC>, // lm
+  ) -> SMatrix<f32, N, M>;
+
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct EkKalmanFilter
+           <const M:  usize,
+            const N:  usize,
+            const C:  usize,
+            const LR: usize,
+            const LC: usize>
+{
+  // フィルタ共通変数定義
+  pub fb: FilterBase<M, N, C>,
+  // 拡張カルマンフィルタ用行列
+  pub Fj: SMatrix<f32, M, M>,  // fxのﾔｺﾋﾞｱﾝ
+  pub Hj: SMatrix<f32, N, M>,  // hxのﾔｺﾋﾞｱﾝ
+  pub zp: SMatrix<f32, N, 1>,  // 観測予測値
+  pub lm: SMatrix<f32, LR, LC>,// ﾗﾝﾄﾞﾏｰｸ
+  // 状態遷移関数(fx)
+  pub fx : FxFn<M, C>,
+  // 観測関数(hx)
+  pub hx : HxFn<M, N, LR, LC>,
+  // ﾔｺﾋﾞｱﾝ関数:Noneは数値ﾔｺﾋﾞｱﾝ
+  pub jacobian_fx: Option<JacobianFxFn<M, C>>,
+  pub jacobian_hx:
+             Option<JacobianHxFn<M, N, LR, LC>>,
+  // 引き算関数(z:観測値)
+  pub residual_z: fn(&SMatrix<f32, 1, N>,
+                     &SMatrix<f32, 1, N>
+                  ) -> SMatrix<f32, 1, N>,
+  // 足し算関数(x:状態)
+  pub state_add : fn(&SMatrix<f32, M, 1>,
+                     &SMatrix<f32, M, 1>
+                  ) -> SMatrix<f32, M, 1>,
+  // 移動量
+  pub dt   : f32,
+  // 数値ﾔｺﾋﾞｱﾝの差分幅(相対)
+  pub jacobian_eps: f32,
+}
+#[allow(non_snake_case)]
+impl<const M:  usize,
+     const N:  usize,
+     const C:  usize,
+     const LR: usize,
+     const LC: usize>
+    EkKalmanFilter<M, N, C, LR, LC>
+{
+  pub fn new() -> Self {
+    Self {
+      fb: FilterBase::<M, N, C>::new(),
+      Fj: SMatrix::<f32, M, M>::identity(),
+      Hj: SMatrix::<f32, N, M>::zeros(),
+      zp: SMatrix::<f32, N, 1>::zeros(),
+      lm: SMatrix::<f32, LR, LC>::zeros(),
+      fx: fx_default,
+      hx: hx_default,
+      jacobian_fx: None,
+      jacobian_hx: None,
+      residual_z : residual_z_default,
+      state_add  : state_add_default,
+      dt: 1.0,
+      jacobian_eps: 1.0e-3,
+    }
+  }
+  // 予測
+  pub fn predict(&mut self) {
+    self.try_predict().unwrap();
+  }
+  pub fn try_predict(&mut self)
+    -> Result<(), FilterError>
+  {
+    // 事後分布xでfxを線形化
+    // Fj = ∂fx/∂x (x)
+    self.Fj = match self.jacobian_fx {
+      Some(jf) => jf(&self.fb.x,
+                     &self.fb.u,
+                     &self.fb.F,
+                     &self.fb.B,
+                     self.dt,
+                  ),
+      None     => self.numeric_jacobian_fx(),
+    };
+    // x = fx(x, u, dt)
+    self.fb.x = (self.fx)(&self.fb.x,
+                          &self.fb.u,
+                          &self.fb.F,
+                          &self.fb.B,
+                          self.dt,
+                );
+    // P = FjPFj' + Q
+    self.fb.P = (self.Fj * self.fb.P) *
+                self.Fj.transpose() +
+                self.fb.Q;
+    self.fb.check_finite()
+  }
+  // 更新
+  pub fn update(&mut self) {
+    self.try_update().unwrap();
+  }
+  pub fn try_update(&mut self)
+    -> Result<(), FilterError>
+  {
+    // 事前分布xでhxを線形化
+    // Hj = ∂hx/∂x (x)
+    self.Hj = match self.jacobian_hx {
+      Some(jh) => jh(&self.fb.x,
+                     &self.fb.H,
+                     &self.lm,
+                  ),
+      None     => self.numeric_jacobian_hx(),
+    };
+    // zp = hx(x)
+    self.zp = (self.hx)(&self.fb.x,
+                        &self.fb.H,
+                        &self.lm,
+              );
+    // y = z - hx(x) : 残差
+    self.fb.y = ((self.residual_z)(
+                   &self.fb.z.transpose(),
+                   &self.zp.transpose(),
+                )).transpose();
+    // S = HjPHj' + R
+    let PHt = self.fb.P *
+              self.Hj.transpose();
+    self.fb.S = self.Hj * PHt + self.fb.R;
+    // 観測ゲート:ゲート外は棄却かR拡大
+    let Some(g) = self.fb.try_gate()? else {
+      return Ok(());
+    };
+    let R = self.fb.R * g;
+    if g != 1.0 {
+      self.fb.S = self.Hj * PHt + R;
+    }
+    // K = PHj'inv(S)
+    // Sが特異なら状態を変えずにエラー返却
+    self.fb.K = PHt *
+                self.fb.S.try_inverse()
+                         .ok_or(
+                   FilterError::SingularInnovation
+                 )?;
+    // x = x + Ky
+    self.fb.x = (self.state_add)(
+                  &self.fb.x,
+                 &(self.fb.K * self.fb.y)
+                );
+    // P = (I-KHj)P(I-KHj)' + KRK'
+    let i_kh = self.fb.I -
+               self.fb.K * self.Hj;
+    self.fb.P = (i_kh * self.fb.P) *
+                i_kh.transpose() +
+                (self.fb.K * R) *
+                self.fb.K.transpose();
+    self.fb.check_finite()
+  }
+  // 数値ﾔｺﾋﾞｱﾝ:fx
+  pub fn numeric_jacobian_fx(&self)
+    -> SMatrix<f32, M, M>
+  {
+    let mut J = SMatrix::<f32, M, M>::zeros();
+    (0..M).for_each(|c| {
+      let (xp, xm, h) = self.perturb(c);
+      let fp = (self.fx)(&xp, &self.fb.u,
+                         &self.fb.F, &self.fb.B,
+                         self.dt);
+      let fm = (self.fx)(&xm, &self.fb.u,
+                         &self.fb.F, &self.fb.B,
+                         self.dt);
+      // (f(x+h)-f(x-h))/2h
+      J.column_mut(c)
+       .copy_from(&((fp - fm) / (2. * h)));
+    });
+    J
+  }
+  // 数値ﾔｺﾋﾞｱﾝ:hx
+  pub fn numeric_jacobian_hx(&self)
+    -> SMatrix<f32, N, M>
+  {
+    let mut J = SMatrix::<f32, N, M>::zeros();
+    (0..M).for_each(|c| {
+      let (xp, xm, h) = self.perturb(c);
+      let hp = (self.hx)(&xp, &self.fb.H,
+                         &self.lm);
+      let hm = (self.hx)(&xm, &self.fb.H,
+                         &self.lm);
+      // 角度等はresidual_zで差を取る
+      let d  = (self.residual_z)(
+                 &hp.transpose(),
+                 &hm.transpose(),
+               );
+      J.column_mut(c)
+       .copy_from(&(d.transpose() / (2. * h)));
+    });
+    J
+  }
+  // 中心差分用にx[c]を±hずらす
+  fn perturb(&self, c: usize)
+    -> (SMatrix<f32, M, 1>,
+        SMatrix<f32, M, 1>,
+        f32)
+  {
+    let h = self.jacobian_eps *
+            self.fb.x[(c, 0)].abs().max(1.0);
+    let mut xp = self.fb.x;
+    let mut xm = self.fb.x;
+    xp[(c, 0)] += h;
+    xm[(c, 0)] -= h;
+    (xp, xm, h)
+  }
+}
+// フィルタ共通トレイト実装
+impl<const M:  usize,
+     const N:  usize,
+     const C:  usize,
+     const LR: usize,
+     const LC: usize>
+    Filter for EkKalmanFilter<M, N, C, LR, LC>
+where
+    Const<N>: DimMin<Const<N>,
+                     Output = Const<N>>,
+{
+  fn step_predict(&mut self)
+    -> Result<(), FilterError>
+  {
+    EkKalmanFilter::try_predict(self)
+  }
+  fn step_update(&mut self)
+    -> Result<(), FilterError>
+  {
+    EkKalmanFilter::try_update(self)
+  }
+  fn base(&self) -> &dyn FilterState {
+    &self.fb
+  }
+  fn base_mut(&mut self)
+    -> &mut dyn FilterState
+  {
+    &mut self.fb
+  }
+  fn dt_set(&mut self, dt: f32) {
+    self.dt = dt;
+  }
+}
+// --- 関数定義型 デフォルト実装 ---
+// 状態遷移関数(fx)デフォルト
+#[allow(non_snake_case)]
+fn fx_default<const M: usize, const C: usize>(
+  x:  &SMatrix<f32, M, 1>,
+  u:  &SMatrix<f32, C, 1>,
+  F:  &SMatrix<f32, M, M>,
+  B:  &SMatrix<f32, M, C>,
+  _dt:f32,
+) -> SMatrix<f32, M, 1>
+{
+  F * x + B * u
+}
+// 観測関数(hx)デフォルト
+#[allow(non_snake_case)]
+fn hx_default
+   <const M:  usize,
+    const N:  usize,
+    const LR: usize,
+    const LC: usize>
+(
+  x:  &SMatrix<f32, M, 1>,
+  H:  &SMatrix<f32, N, M>,
+  _lm:&SMatrix<f32, LR, LC>,
+) -> SMatrix<f32, N, 1>
+{
+  H * x
+}
+// 引き算関数(z:観測値)デフォルト
+fn residual_z_default<const N: usize>(
+  a: &SMatrix<f32, 1, N>,
+  b: &SMatrix<f32, 1, N>
+) -> SMatrix<f32, 1, N>
+{
+  a - b
+}
+// 足し算関数(x:状態)デフォルト
+fn state_add_default<const M: usize>(
+  a: &SMatrix<f32, M, 1>,
+  b: &SMatrix<f32, M, 1>
+) -> SMatrix<f32, M, 1>
+{
+  a + b
+}
+// --- Clippy対応 ---
+impl<const M:  usize,
+     const N:  usize,
+     const C:  usize,
+     const LR: usize,
+     const LC: usize>
+    Default for EkKalmanFilter<M, N, C, LR, LC> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,226 @@
+#![no_std]
+// フィルタ整合性分析(NEES, NIS)
+//
+// # NEES(正規化推定誤差二乗)
+//    e    = x - x_true
+//    NEES = e.T @ inv(P) @ e    ~ χ²(m)
+//    シミュレータの真値(ConstantAcc::read,
+//    Robot2d::read, ACSim::update等)と比べる。
+//
+// # NIS(正規化残差二乗)
+//    NIS  = y.T @ inv(S) @ y    ~ χ²(n)
+//    FilterBaseのy, Sから算出(真値不要)。
+//
+// # 平均と信頼区間
+//    T回分の値の合計はχ²(Σdof)に従うので
+//    平均 = 合計 / T の両側信頼区間は
+//      下限 = χ²((1-conf)/2, Σdof) / T
+//      上限 = χ²((1+conf)/2, Σdof) / T
+//    平均が区間内なら整合している。
+//    Pが小さすぎる(過信)と平均が上限を超え、
+//    大きすぎる(悲観)と下限を下回る。
+//    個々の値が片側閾値χ²(conf, dof)以内に
+//    入った割合(inside_ratio)はconf程度になる。
+//
+// 使い方
+//   let mut ca = Consistency::new(0.95);
+//   loop {
+//     kf.predict(); kf.update();
+//     ca.push_nees(&kf.fb.x, &kf.fb.P, &xt)?;
+//     ca.push_nis_fb(&kf.fb)?;
+//   }
+//   ca.is_consistent()
+// -----------------------------------------
+use filter_base::*;
+use nalgebra::SMatrix;
+
+// χ²統計量の集計
+#[derive(Clone, Debug)]
+pub struct ChiStat {
+  pub conf  : f32,   // 信頼度(0.95等)
+  sum       : f32,   // 値の合計
+  dof       : usize, // 自由度の合計
+  count     : usize, // 件数
+  inside    : usize, // 片側閾値以内の件数
+}
+// 集計結果
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChiReport {
+  pub count : usize, // 件数
+  pub mean  : f32,   // 平均
+  pub expect: f32,   // 期待値(平均自由度)
+  pub lower : f32,   // 平均の信頼区間:下限
+  pub upper : f32,   // 平均の信頼区間:上限
+  pub inside_ratio: f32, // 閾値以内の割合
+  pub consistent  : bool,// 整合(区間内)
+}
+impl ChiStat {
+  pub fn new(conf: f32) -> Self {
+    assert!(0.0 < conf && conf < 1.0,
+            // 0 < conf < 1 じゃないとダメ
+            "0 < conf < 1, otherwise.");
+    Self {
+      conf,
+      sum   : 0.0,
+      dof   : 0,
+      count : 0,
+      inside: 0,
+    }
+  }
+  // 値追加(dof:自由度)
+  pub fn push(&mut self, v: f32, dof: usize) {
+    self.sum   += v;
+    self.dof   += dof;
+    self.count += 1;
+    if v <= chi2_quantile(dof, self.conf) {
+      self.inside += 1;
+    }
+  }
+  // 件数
+  pub fn count(&self) -> usize {
+    self.count
+  }
+  // 平均
+  pub fn mean(&self) -> f32 {
+    if self.count == 0 {
+      return 0.0;
+    }
+    self.sum / self.count as f32
+  }
+  // 平均の両側信頼区間
+  pub fn bounds(&self) -> (f32, f32) {
+    if self.count == 0 {
+      return (0.0, 0.0);
+    }
+    let t = self.count as f32;
+    let a = (1.0 - self.conf) * 0.5;
+    (chi2_quantile(self.dof, a) / t,
+     chi2_quantile(self.dof, 1.0 - a) / t)
+  }
+  // 整合しているか(件数0はtrue)
+  pub fn is_consistent(&self) -> bool {
+    let (l, u) = self.bounds();
+    let m = self.mean();
+    self.count == 0 || (l <= m && m <= u)
+  }
+  // 集計結果
+  pub fn report(&self) -> ChiReport {
+    let (lower, upper) = self.bounds();
+    let n = self.count.max(1) as f32;
+    ChiReport {
+      count : self.count,
+      mean  : self.mean(),
+      expect: self.dof as f32 / n,
+      lower,
+      upper,
+      inside_ratio: self.inside as f32 / n,
+      consistent  : self.is_consistent(),
+    }
+  }
+  // 初期化
+  pub fn reset(&mut self) {
+    self.sum    = 0.0;
+    self.dof    = 0;
+    self.count  = 0;
+    self.inside = 0;
+  }
+}
+// NEES, NISの集計 ==========================
+#[derive(Clone, Debug)]
+pub struct Consistency {
+  pub nees: ChiStat,
+  pub nis : ChiStat,
+}
+#[allow(non_snake_case)]
+impl Consistency {
+  pub fn new(conf: f32) -> Self {
+    Self {
+      nees: ChiStat::new(conf),
+      nis : ChiStat::new(conf),
+    }
+  }
+  // NEES追加(状態全体)
+  pub fn push_nees<const M: usize>(
+    &mut self,
+    x : &SMatrix<f32, M, 1>,
+    P : &SMatrix<f32, M, M>,
+    xt: &SMatrix<f32, M, 1>, // 真値
+  ) -> Result<f32, FilterError>
+  {
+    let v = nees(&(x - xt), P)?;
+    self.nees.push(v, M);
+    Ok(v)
+  }
+  // NEES追加(状態の一部)
+  // 真値が位置だけ等の場合に使う
+  //   idx: 真値に対応する状態の添字
+  //   例:x = [px, vx, py, vy], 真値 = [px, py]
+  //       idx = [0, 2]
+  pub fn push_nees_idx<const K: usize>(
+    &mut self,
+    f  : &dyn Filter,
+    xt : &[f32; K],     // 真値
+    idx: &[usize; K],
+  ) -> Result<f32, FilterError>
+  {
+    let e = SMatrix::<f32, K, 1>::from_fn(|r, _|
+              f.x(idx[r]) - xt[r]
+            );
+    let P = SMatrix::<f32, K, K>::from_fn(|r, c|
+              f.P(idx[r], idx[c])
+            );
+    let v = nees(&e, &P)?;
+    self.nees.push(v, K);
+    Ok(v)
+  }
+  // NIS追加(y, S)
+  pub fn push_nis<const N: usize>(
+    &mut self,
+    y: &SMatrix<f32, N, 1>,
+    S: &SMatrix<f32, N, N>,
+  ) -> Result<f32, FilterError>
+  {
+    let v = nees(y, S)
+              .map_err(|_|
+                 FilterError::SingularInnovation
+              )?;
+    self.nis.push(v, N);
+    Ok(v)
+  }
+  // NIS追加(FilterBase)
+  // update後に呼出す
+  pub fn push_nis_fb<const M: usize,
+                     const N: usize,
+                     const C: usize>(
+    &mut self,
+    fb: &FilterBase<M, N, C>,
+  ) -> Result<f32, FilterError>
+  {
+    self.push_nis(&fb.y, &fb.S)
+  }
+  // NEES, NIS両方整合しているか
+  pub fn is_consistent(&self) -> bool {
+    self.nees.is_consistent() &&
+    self.nis.is_consistent()
+  }
+  // 初期化
+  pub fn reset(&mut self) {
+    self.nees.reset();
+    self.nis.reset();
+  }
+}
+// 正規化二乗 e.T @ inv(P) @ e
+// Pが正定値でなければエラー
+#[allow(non_snake_case)]
+pub fn nees<const K: usize>(
+  e: &SMatrix<f32, K, 1>,
+  P: &SMatrix<f32, K, K>,
+) -> Result<f32, FilterError>
+{
+  let ch = P.cholesky()
+            .ok_or(
+              FilterError::NotPositiveDefinite
+            )?;
+  Ok(*(e.transpose() * ch.solve(e))
+      .as_scalar())
+}
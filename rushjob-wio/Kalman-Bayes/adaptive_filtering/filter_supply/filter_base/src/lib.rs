@@ -1,9 +1,42 @@
 #![no_std]
 use nalgebra::{SMatrix, DimMin, Const};
 use core::f32::consts::PI;
+use filter_snapshot::{SnapWriter, SnapReader,
+                      snapshot_len};
+pub use filter_snapshot::SnapshotError;
 #[allow(unused_imports)]
 use micromath::F32Ext;
 
+// フィルタエラー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterError {
+  // 発展共分散行列Sが逆行列を持たない
+  SingularInnovation,
+  // 共分散行列Pが正定値でない
+  // (コレスキー分解失敗)
+  NotPositiveDefinite,
+  // 状態変数/共分散行列にNaN,infが発生
+  NonFinite,
+  // 予測共分散行列が逆行列を持たない
+  // (平滑化等で使用)
+  SingularCovariance,
+  // 観測時刻が履歴より古く処理できない
+  // (時刻付き処理で使用)
+  OutOfSequence,
+}
+// 観測ゲート判定結果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GateState {
+  // ゲート未設定
+  Unchecked,
+  // ゲート内:通常更新
+  Inside,
+  // ゲート外:更新せず棄却
+  Rejected,
+  // ゲート外:Rを拡大して更新
+  Inflated,
+}
+
 #[derive(Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct FilterBase<const M: usize,
@@ -24,20 +57,17 @@ pub struct FilterBase<const M: usize,
   pub S: SMatrix<f32, N, N>,// 発展共分散行列
   pub I: SMatrix<f32, M, M>,// 単位行列
   pub alpha   : Option<f32>,// 減衰記憶ﾊﾟﾗﾒｰﾀ
-  pub cum_lh  : Option<f32>,// 累積尤度
+  pub cum_log_lh: Option<f32>,// 累積対数尤度
   pub ns_count: Option<usize>,// ﾉｲｽﾞ調整ｶｳﾝﾄ
   pub phi     : Option<f32>,// ﾉｲｽﾞ分散値
-  pub noise_adjust_fn:      // ﾌﾟﾛｾｽﾉｲｽﾞ調整
-      fn (&mut SMatrix<f32, M, M>, // Q
-              &SMatrix<f32, N, 1>, // y
-              &SMatrix<f32, N, N>, // S
-          &mut f32,                // eps,etc
-          &mut usize,              // ns_count
-          f32,  // Q_scale_factor
-          f32,  // Q_adjust_param
-         ),
+  pub noise_adjust_fn:      // ﾉｲｽﾞ調整
+      fn (&mut NoiseContext<M, N>),
   pub Q_scale_factor: f32, // ﾉｲｽﾞ調整係数
   pub Q_adjust_param: f32, // ﾉｲｽﾞ調整閾値
+  pub noise   : NoiseState<N>,// ﾉｲｽﾞ推定状態
+  pub gate    : Option<f32>,// ｹﾞｰﾄ閾値(χ²)
+  pub gate_inflate: bool,   // ｹﾞｰﾄ外:R拡大
+  pub gate_state: GateState,// ｹﾞｰﾄ判定結果
 }
 #[allow(non_snake_case)]
 impl <const M: usize,
@@ -61,13 +91,17 @@ impl <const M: usize,
       S: SMatrix::<f32, N, N> ::zeros(),
       I: SMatrix::<f32, M, M> ::identity(),
       alpha   : None,
-      cum_lh  : None,
+      cum_log_lh: None,
       ns_count: None,
       phi     : None,
       noise_adjust_fn:
                    noise_adjust_fn_default,
       Q_scale_factor: 0.0,
       Q_adjust_param: 0.0,
+      noise   : NoiseState::<N>::new(),
+      gate    : None,
+      gate_inflate: false,
+      gate_state: GateState::Unchecked,
     }
   }
   // 減衰記憶計算
@@ -78,9 +112,14 @@ impl <const M: usize,
     }
   }
   // 累積尤度値取得
+  // (累積対数尤度のexp、未設定は0)
   pub fn cum_lh(&self) -> f32 {
-    if let Some(c) = self.cum_lh {c} 
-                            else {0.0}
+    self.cum_log_lh.map_or(0.0, libm_exp)
+  }
+  // 累積尤度値設定(事前確率等)
+  // 対数にして保持する(lh = 0は-∞)
+  pub fn cum_lh_set(&mut self, lh: f32) {
+    self.cum_log_lh = Some(libm_ln(lh));
   }
   // ﾌﾟﾛｾｽﾉｲｽﾞ調整
   pub fn noise_adjust(&mut self) {
@@ -91,15 +130,19 @@ impl <const M: usize,
                          } else {
                            self.y_eps()
                          };
-      (self.noise_adjust_fn)(
-         &mut self.Q,
-         &self.y,
-         &self.S,
-         &mut val,
-         &mut n,
-         self.Q_scale_factor,
-         self.Q_adjust_param,
-      );
+      let f = self.noise_adjust_fn;
+      f(&mut NoiseContext {
+        Q       : &mut self.Q,
+        R       : &mut self.R,
+        y       : &self.y,
+        S       : &self.S,
+        K       : &self.K,
+        eps     : &mut val,
+        ns_count: &mut n,
+        Q_scale_factor: self.Q_scale_factor,
+        Q_adjust_param: self.Q_adjust_param,
+        state   : &mut self.noise,
+      });
       self.ns_count = Some(n);
       if self.phi.is_some() {
         self.phi = Some(val);
@@ -108,33 +151,367 @@ impl <const M: usize,
   }
   // 正規化された残差 
   pub fn y_eps(&self) -> f32 {
+    self.try_y_eps().unwrap()
+  }
+  pub fn try_y_eps(&self) 
+    -> Result<f32, FilterError> 
+  {
     // y.T*S^-1*y
-    *(self.y.transpose() * 
-      self.S.try_inverse().unwrap() * 
-      self.y
-    ).as_scalar()
+    let si = self.S.try_inverse()
+                 .ok_or(
+                   FilterError::SingularInnovation
+                 )?;
+    Ok(*(self.y.transpose() * si * self.y)
+        .as_scalar())
+  }
+  // 観測ゲート設定
+  // conf: 信頼度(0.99等)
+  // inflate: false:棄却, true:R拡大
+  pub fn gate_set(&mut self,
+                  conf: f32, inflate: bool) {
+    self.gate = Some(chi2_threshold(N, conf));
+    self.gate_inflate = inflate;
+  }
+  // 観測ゲート判定
+  // y, S算出後、状態更新前に呼出す
+  //   d² = y.T*S^-1*y > gate ならゲート外
+  // 戻り値
+  //   Some(s): 更新する(Rの倍率s)
+  //            ゲート外でR拡大の場合
+  //            s = d² / gate
+  //   None   : 棄却(更新しない)
+  pub fn try_gate(&mut self)
+    -> Result<Option<f32>, FilterError>
+  {
+    let Some(g) = self.gate else {
+      self.gate_state = GateState::Unchecked;
+      return Ok(Some(1.0));
+    };
+    let d2 = self.try_y_eps()?;
+    if d2 <= g {
+      self.gate_state = GateState::Inside;
+      Ok(Some(1.0))
+    } else if self.gate_inflate {
+      self.gate_state = GateState::Inflated;
+      Ok(Some(d2 / g))
+    } else {
+      self.gate_state = GateState::Rejected;
+      Ok(None)
+    }
+  }
+  // 状態変数、共分散行列の有限値チェック
+  pub fn check_finite(&self) 
+    -> Result<(), FilterError> 
+  {
+    if self.x.iter().all(|v| v.is_finite()) &&
+       self.P.iter().all(|v| v.is_finite()) {
+      Ok(())
+    } else {
+      Err(FilterError::NonFinite)
+    }
   }
 }
-// 関数定義型:ﾌﾟﾛｾｽﾉｲｽﾞ調整ﾃﾞﾌｫﾙﾄ実装(例)
+// χ²分布の閾値(上側確率 1-conf の点)
+// ゲート用。0.5 < conf < 1
+pub fn chi2_threshold(n: usize, conf: f32)
+  -> f32
+{
+  assert!(0.5 < conf && conf < 1.0,
+          // 0.5 < conf < 1 じゃないとダメ
+          "0.5 < conf < 1, otherwise.");
+  chi2_quantile(n, conf)
+}
+// χ²分布の分位点(下側確率 p の点)
+//   n = 1: z((1+p)/2)²
+//   n = 2: -2ln(1-p)
+//   n >= 3: Wilson-Hilferty近似
+//     n(1 - 2/9n + z(p)√(2/9n))³
+//   z: 標準正規分布の分位点
+//      (Abramowitz-Stegun 26.2.23)
+// ※micromathのln,sqrtは近似値のため
+//   精度の高いlibm版(ComplexField)を使用
+pub fn chi2_quantile(n: usize, p: f32) -> f32 {
+  assert!(0.0 < p && p < 1.0,
+          // 0 < p < 1 じゃないとダメ
+          "0 < p < 1, otherwise.");
+  match n {
+    0 => 0.0,
+    1 => norm_ppf((1.0 + p) * 0.5).powi(2),
+    2 => -2.0 * libm_ln(1.0 - p),
+    _ => {
+      let k = 2.0 / (9.0 * n as f32);
+      n as f32 *
+      (1.0 - k + norm_ppf(p) * libm_sqrt(k))
+      .max(0.0)
+      .powi(3)
+    }
+  }
+}
+// 標準正規分布の分位点(0 < p < 1)
+pub fn norm_ppf(p: f32) -> f32 {
+  if p < 0.5 {
+    return -norm_ppf(1.0 - p);
+  }
+  let t = libm_sqrt(-2.0 * libm_ln(1.0 - p));
+  t - (2.515517 + 0.802853 * t +
+       0.010328 * t * t) /
+      (1.0 + 1.432788 * t +
+       0.189269 * t * t +
+       0.001308 * t * t * t)
+}
+fn libm_sqrt(v: f32) -> f32 {
+  <f32 as nalgebra::ComplexField>::sqrt(v)
+}
+fn libm_ln(v: f32) -> f32 {
+  <f32 as nalgebra::ComplexField>::ln(v)
+}
+fn libm_exp(v: f32) -> f32 {
+  <f32 as nalgebra::ComplexField>::exp(v)
+}
+// log(Σexp(v[i]))
+// 最大値を括り出して桁あふれを防ぐ
+//   m + log(Σexp(v[i] - m))
+// 全て-∞(空)なら-∞
+pub fn log_sum_exp(v: &[f32]) -> f32 {
+  let m = v.iter()
+           .fold(f32::NEG_INFINITY, |m, x|
+             m.max(*x)
+           );
+  if m == f32::NEG_INFINITY {
+    return m;
+  }
+  m + libm_ln(v.iter()
+               .map(|x| libm_exp(x - m))
+               .sum())
+}
+// 対数重みを正規化した確率に変換
+//   p[i] = exp(v[i] - log_sum_exp(v))
+//   p[i] = max(p[i], p_min)で再正規化
+// 全て-∞なら一様分布
+pub fn log_normalize(v: &[f32],
+                     p: &mut [f32],
+                     p_min: f32)
+{
+  let lse = log_sum_exp(v);
+  let n   = p.len() as f32;
+  p.iter_mut()
+   .zip(v.iter())
+   .for_each(|(p, v)|
+     *p = if lse == f32::NEG_INFINITY {
+            1.0 / n
+          } else {
+            libm_exp(v - lse).max(p_min)
+          }
+   );
+  let s: f32 = p.iter().sum();
+  p.iter_mut().for_each(|p| *p /= s);
+}
+// ノイズ推定の観測残差履歴数(最大)
+pub const NOISE_WINDOW: usize = 16;
+// ノイズ推定状態
+// (適応ノイズ推定の関数が使う)
+#[derive(Clone, Debug)]
+pub struct NoiseState<const N: usize> {
+  pub window : usize, // 窓長(<= NOISE_WINDOW)
+  pub forget : f32,   // 忘却係数(0 < b < 1)
+  pub adapt_q: bool,  // Qを推定する
+  pub adapt_r: bool,  // Rを推定する
+  pub count  : usize, // 推定回数
+  // 観測残差履歴(リングバッファ)
+  pub ys     : [SMatrix<f32, N, 1>; NOISE_WINDOW],
+}
+impl<const N: usize> NoiseState<N> {
+  pub fn new() -> Self {
+    Self {
+      window : NOISE_WINDOW,
+      forget : 0.98,
+      adapt_q: true,
+      adapt_r: true,
+      count  : 0,
+      ys     : [SMatrix::<f32, N, 1>::zeros();
+                NOISE_WINDOW],
+    }
+  }
+  // 観測残差追加
+  pub fn push(&mut self, y: &SMatrix<f32, N, 1>) {
+    self.ys[self.count % NOISE_WINDOW] = *y;
+    self.count += 1;
+  }
+  // 直近window件の y @ y.T の平均
+  // window件に満たなければNone
+  pub fn innovation_cov(&self)
+    -> Option<SMatrix<f32, N, N>>
+  {
+    let w = self.window.clamp(1, NOISE_WINDOW);
+    if self.count < w {
+      return None;
+    }
+    let c = (0..w).fold(
+              SMatrix::<f32, N, N>::zeros(),
+              |c, i| {
+                let y = self.ys[
+                          (self.count - 1 - i) %
+                          NOISE_WINDOW
+                        ];
+                c + y * y.transpose()
+              }
+            );
+    Some(c / w as f32)
+  }
+  // 初期化
+  pub fn reset(&mut self) {
+    self.count = 0;
+  }
+}
+// ノイズ調整関数に渡す値
 #[allow(non_snake_case)]
+pub struct NoiseContext<'a,
+                        const M: usize,
+                        const N: usize>
+{
+  pub Q: &'a mut SMatrix<f32, M, M>,// ﾌﾟﾛｾｽﾉｲｽﾞ
+  pub R: &'a mut SMatrix<f32, N, N>,// 観測ノイズ
+  pub y: &'a SMatrix<f32, N, 1>,    // 残差
+  pub S: &'a SMatrix<f32, N, N>,    // 発展共分散
+  pub K: &'a SMatrix<f32, M, N>,    // ｶﾙﾏﾝｹﾞｲﾝ
+  pub eps     : &'a mut f32,  // phi(未設定はeps)
+  pub ns_count: &'a mut usize,// ﾉｲｽﾞ調整ｶｳﾝﾄ
+  pub Q_scale_factor: f32,    // ﾉｲｽﾞ調整係数
+  pub Q_adjust_param: f32,    // ﾉｲｽﾞ調整閾値
+  pub state: &'a mut NoiseState<N>,// 推定状態
+}
+// 関数定義型:ﾌﾟﾛｾｽﾉｲｽﾞ調整ﾃﾞﾌｫﾙﾄ実装(例)
+// eps > Q_adjust_param ならQをQ_scale_factor倍
 pub fn noise_adjust_fn_default
        <const M: usize, const N: usize>
 (
-  Q: &mut SMatrix<f32, M, M>, // Q
-  _:     &SMatrix<f32, N, 1>, // y
-  _:     &SMatrix<f32, N, N>, // S
-  eps     : &mut f32,         // eps
-  ns_count: &mut usize,       // ns_count
-  Q_scale_factor: f32,
-  eps_max : f32,
+  c: &mut NoiseContext<M, N>,
 )
 {
-  if *eps > eps_max {
-    *Q *= Q_scale_factor;
-    *ns_count += 1;
-  } else if *ns_count > 0 {
-    *Q /= Q_scale_factor;
-    *ns_count -= 1;
+  if *c.eps > c.Q_adjust_param {
+    *c.Q *= c.Q_scale_factor;
+    *c.ns_count += 1;
+  } else if *c.ns_count > 0 {
+    *c.Q /= c.Q_scale_factor;
+    *c.ns_count -= 1;
+  }
+}
+// スナップショット(保存/復元) ================
+// FilterBaseの状態(x, P, Q, R, 適応ﾊﾟﾗﾒｰﾀ)を
+// 呼出し側のバッファへバイナリで保存する。
+// F, H, B, 関数等のモデル定義は保存しない。
+//
+// # 形式(filter_snapshot)
+//   magic  : "KFSN"
+//   version: SNAPSHOT_VERSION
+//   次元   : M, N, C
+//
+// # ペイロード
+//   x, P, Q, R (f32, 列優先)
+//   alpha, cum_log_lh, ns_count(u32), phi
+//     (Option: 有無1 + 値4)
+//   Q_scale_factor, Q_adjust_param
+//   gate(Option), gate_inflate(1)
+//   noise: window(u32), forget, adapt_q(1),
+//          adapt_r(1), count(u32), ys
+//
+//  復元はヘッダ、長さ、CRCを確認してから
+//  値を書き換える(エラー時は変更しない)。
+//  SR-UKFのSx、情報フィルタのY等はPから
+//  再計算されるのでFilterBaseだけでよい。
+// スナップショットのmagic
+const SNAPSHOT_MAGIC: &[u8; 4] = b"KFSN";
+impl<const M: usize,
+     const N: usize,
+     const C: usize>
+    FilterBase<M, N, C>
+{
+  // スナップショット形式のバージョン
+  pub const SNAPSHOT_VERSION: u16 = 1;
+  // ペイロード長
+  pub const SNAPSHOT_PAYLOAD: usize =
+    4 * (M + 2 * M * M + N * N) +
+    5 * 4 + 4 * 2 + 5 + 1 +
+    4 + 4 + 1 + 1 + 4 +
+    4 * N * NOISE_WINDOW;
+  // 全体長(バッファサイズ)
+  //   [0u8; FilterBase::<M, N, C>::SNAPSHOT_LEN]
+  pub const SNAPSHOT_LEN: usize =
+    snapshot_len(3, Self::SNAPSHOT_PAYLOAD);
+  // 保存
+  // 戻り値:書込んだバイト数
+  pub fn save(&self, buf: &mut [u8])
+    -> Result<usize, SnapshotError>
+  {
+    let mut w = SnapWriter::new(
+                  buf,
+                  SNAPSHOT_MAGIC,
+                  Self::SNAPSHOT_VERSION,
+                  [M, N, C],
+                  Self::SNAPSHOT_PAYLOAD,
+                )?;
+    self.x.iter().for_each(|v| w.f32(*v));
+    self.P.iter().for_each(|v| w.f32(*v));
+    self.Q.iter().for_each(|v| w.f32(*v));
+    self.R.iter().for_each(|v| w.f32(*v));
+    w.opt_f32(self.alpha);
+    w.opt_f32(self.cum_log_lh);
+    w.opt_u32(self.ns_count.map(|n| n as u32));
+    w.opt_f32(self.phi);
+    w.f32(self.Q_scale_factor);
+    w.f32(self.Q_adjust_param);
+    w.opt_f32(self.gate);
+    w.u8(self.gate_inflate as u8);
+    w.u32(self.noise.window as u32);
+    w.f32(self.noise.forget);
+    w.u8(self.noise.adapt_q as u8);
+    w.u8(self.noise.adapt_r as u8);
+    w.u32(self.noise.count as u32);
+    self.noise.ys.iter()
+        .flat_map(|y| y.iter())
+        .for_each(|v| w.f32(*v));
+    Ok(w.finish())
+  }
+  // 復元
+  // 戻り値:読込んだバイト数
+  pub fn restore(&mut self, buf: &[u8])
+    -> Result<usize, SnapshotError>
+  {
+    let mut r = SnapReader::new(
+                  buf,
+                  SNAPSHOT_MAGIC,
+                  Self::SNAPSHOT_VERSION,
+                  [M, N, C],
+                  Self::SNAPSHOT_PAYLOAD,
+                )?;
+    self.x.iter_mut().for_each(|v| *v = r.f32());
+    self.P.iter_mut().for_each(|v| *v = r.f32());
+    self.Q.iter_mut().for_each(|v| *v = r.f32());
+    self.R.iter_mut().for_each(|v| *v = r.f32());
+    self.alpha      = r.opt_f32();
+    self.cum_log_lh = r.opt_f32();
+    self.ns_count   = r.opt_u32()
+                       .map(|n| n as usize);
+    self.phi        = r.opt_f32();
+    self.Q_scale_factor = r.f32();
+    self.Q_adjust_param = r.f32();
+    self.gate         = r.opt_f32();
+    self.gate_inflate = r.u8() != 0;
+    self.noise.window  = r.u32() as usize;
+    self.noise.forget  = r.f32();
+    self.noise.adapt_q = r.u8() != 0;
+    self.noise.adapt_r = r.u8() != 0;
+    self.noise.count   = r.u32() as usize;
+    self.noise.ys.iter_mut()
+        .flat_map(|y| y.iter_mut())
+        .for_each(|v| *v = r.f32());
+    Ok(r.finish())
+  }
+}
+// --- Clippy対応 ---
+impl<const N: usize> Default for NoiseState<N> {
+  fn default() -> Self {
+    Self::new()
   }
 }
 // determinantを使用のためimpl分離が必要
@@ -149,165 +526,366 @@ where
   // 尤度計算
   pub fn likelihood(&self) -> f32 {
     // 1/√(2π^n * |S|) * exp(-1/2*y.T*S^-1*y)
-    1. / ((2. * PI).powi(N as i32) *
-          self.S.determinant()
-         ).sqrt() *
-    (-0.5 * self.y_eps()).exp()
+    libm_exp(self.log_likelihood())
   }
   // 対数尤度計算
+  // 尤度を直接計算すると小さな値でf32が
+  // アンダーフローするので対数で計算する
+  //   -1/2*(n*log(2π) + log|S| + y.T*S^-1*y)
   pub fn log_likelihood(&self) -> f32 {
-    self.likelihood().ln()
+    -0.5 * (N as f32 * libm_ln(2. * PI) +
+            libm_ln(self.S.determinant()) +
+            self.y_eps())
   }
-  // 累積尤度計算
+  // 累積尤度計算(対数の和)
   pub fn cum_likelihood(&mut self) {
-    if let Some(c) = self.cum_lh {
-      self.cum_lh = Some(c *
-                         self.likelihood()
-                    );
+    if let Some(c) = self.cum_log_lh {
+      self.cum_log_lh = Some(c +
+                             self.log_likelihood()
+                        );
     }
   }
 }
 
-// フィルタ供給enum用implマクロ ==============
-#[macro_export]
-macro_rules! filter_supply_impl 
+// 次元を隠蔽したFilterBase操作 =============
+// 次元(M,N,C)の異なるフィルタを同じ型で
+// 扱うため、行列はスライスで受け渡す。
+#[allow(non_snake_case)]
+pub trait FilterState {
+  // 状態変数
+  fn x_as_slice(&self) -> &[f32];
+  fn x_as_mut_slice(&mut self) -> &mut [f32];
+  // 状態共分散行列
+  fn P_as_slice(&self) -> &[f32];
+  fn P_as_mut_slice(&mut self) -> &mut [f32];
+  // 状態遷移行列
+  fn F_as_mut_slice(&mut self) -> &mut [f32];
+  // プロセスノイズ行列
+  fn Q_as_mut_slice(&mut self) -> &mut [f32];
+  // 制御入力
+  fn u_as_mut_slice(&mut self) -> &mut [f32];
+  // 観測値
+  fn z_as_mut_slice(&mut self) -> &mut [f32];
+  // 残差
+  fn y_as_slice(&self) -> &[f32];
+  // 観測ノイズ行列
+  fn R_as_slice(&self) -> &[f32];
+  // 発展共分散行列
+  fn S_as_slice(&self) -> &[f32];
+  // 正規化された残差 y.T*S^-1*y
+  fn y_eps(&self) -> f32;
+  // 尤度
+  fn likelihood(&self) -> f32;
+  // 対数尤度
+  fn log_likelihood(&self) -> f32;
+  // 累積尤度値
+  fn cum_lh(&self) -> f32;
+  fn cum_lh_set(&mut self, lh: f32);
+  // 累積対数尤度値
+  fn cum_log_lh(&self) -> f32;
+  fn cum_log_lh_set(&mut self, llh: f32);
+  // 観測ゲート判定結果
+  fn gate_state(&self) -> GateState;
+  // 予測後処理:減衰記憶
+  fn after_predict(&mut self);
+  // 更新後処理:累積尤度、ﾌﾟﾛｾｽﾉｲｽﾞ調整
+  fn after_update(&mut self);
+  // スナップショット保存/復元
+  fn save(&self, buf: &mut [u8])
+    -> Result<usize, SnapshotError>;
+  fn restore(&mut self, buf: &[u8])
+    -> Result<usize, SnapshotError>;
+}
+#[allow(non_snake_case)]
+impl<const M: usize,
+     const N: usize,
+     const C: usize>
+    FilterState for FilterBase<M, N, C>
+where
+    Const<N>: DimMin<Const<N>,
+                     Output = Const<N>>,
 {
- ($($kf:ident,)*) => {
+  fn x_as_slice(&self) -> &[f32] {
+    self.x.as_slice()
+  }
+  fn x_as_mut_slice(&mut self) -> &mut [f32] {
+    self.x.as_mut_slice()
+  }
+  fn P_as_slice(&self) -> &[f32] {
+    self.P.as_slice()
+  }
+  fn P_as_mut_slice(&mut self) -> &mut [f32] {
+    self.P.as_mut_slice()
+  }
+  fn F_as_mut_slice(&mut self) -> &mut [f32] {
+    self.F.as_mut_slice()
+  }
+  fn Q_as_mut_slice(&mut self) -> &mut [f32] {
+    self.Q.as_mut_slice()
+  }
+  fn u_as_mut_slice(&mut self) -> &mut [f32] {
+    self.u.as_mut_slice()
+  }
+  fn z_as_mut_slice(&mut self) -> &mut [f32] {
+    self.z.as_mut_slice()
+  }
+  fn y_as_slice(&self) -> &[f32] {
+    self.y.as_slice()
+  }
+  fn R_as_slice(&self) -> &[f32] {
+    self.R.as_slice()
+  }
+  fn S_as_slice(&self) -> &[f32] {
+    self.S.as_slice()
+  }
+  fn y_eps(&self) -> f32 {
+    FilterBase::y_eps(self)
+  }
+  fn likelihood(&self) -> f32 {
+    FilterBase::likelihood(self)
+  }
+  fn log_likelihood(&self) -> f32 {
+    FilterBase::log_likelihood(self)
+  }
+  fn cum_lh(&self) -> f32 {
+    FilterBase::cum_lh(self)
+  }
+  fn cum_lh_set(&mut self, lh: f32) {
+    FilterBase::cum_lh_set(self, lh);
+  }
+  fn cum_log_lh(&self) -> f32 {
+    self.cum_log_lh.unwrap_or(f32::NEG_INFINITY)
+  }
+  fn cum_log_lh_set(&mut self, llh: f32) {
+    self.cum_log_lh = Some(llh);
+  }
+  fn gate_state(&self) -> GateState {
+    self.gate_state
+  }
+  fn after_predict(&mut self) {
+    self.attenuation();
+  }
+  fn after_update(&mut self) {
+    self.cum_likelihood();
+    // 棄却した観測でノイズ調整しない
+    if self.gate_state != GateState::Rejected {
+      self.noise_adjust();
+    }
+  }
+  fn save(&self, buf: &mut [u8])
+    -> Result<usize, SnapshotError>
+  {
+    FilterBase::save(self, buf)
+  }
+  fn restore(&mut self, buf: &[u8])
+    -> Result<usize, SnapshotError>
+  {
+    FilterBase::restore(self, buf)
+  }
+}
+// フィルタ共通トレイト ======================
+// KalmanFilter、UsKalmanFilter等で実装し、
+// &mut dyn Filter やジェネリクスで
+// フィルタを同じように操作できるようにする。
+// 実装側はstep_predict/step_updateと
+// FilterBaseの参照だけを用意すればよい。
+#[allow(non_snake_case)]
+pub trait Filter {
+// --実装必須--
+  // フィルタ固有の予測
+  fn step_predict(&mut self) 
+    -> Result<(), FilterError>;
+  // フィルタ固有の更新
+  fn step_update(&mut self) 
+    -> Result<(), FilterError>;
+  // FilterBase参照
+  fn base(&self) -> &dyn FilterState;
+  fn base_mut(&mut self) -> &mut dyn FilterState;
 // --メソッド--
   // 予測
-  pub fn predict(&mut self) {
-    match self {
-      $(Self::$kf(f) => {
-          f.predict();
-          f.fb.attenuation();
-      },)*
-    }
+  fn predict(&mut self) {
+    self.try_predict().unwrap();
+  }
+  fn try_predict(&mut self) 
+    -> Result<(), FilterError> 
+  {
+    self.step_predict()?;
+    self.base_mut().after_predict();
+    Ok(())
   }
   // 更新
-  pub fn update(&mut self) {
-    match self {
-      $(Self::$kf(f) => {
-          f.update();
-          f.fb.cum_likelihood();
-          f.fb.noise_adjust();
-      },)*
-    }
+  fn update(&mut self) {
+    self.try_update().unwrap();
+  }
+  fn try_update(&mut self) 
+    -> Result<(), FilterError> 
+  {
+    self.step_update()?;
+    self.base_mut().after_update();
+    Ok(())
   }
 //-- 値設定 --
   // 状態変数
-  pub fn x_set(&mut self, r: usize, s: f32) {
-    match self {
-      $(Self::$kf(f) => f.fb.x[(r, 0)] = s,)*
-    }
+  fn x_set(&mut self, r: usize, s: f32) {
+    self.base_mut().x_as_mut_slice()[r] = s;
   }
-  pub fn x_from_slice(&mut self, s: &[f32])
-  {
-    match self {
-      $(Self::$kf(f) => 
-                 f.fb.x.copy_from_slice(s),)*
-    }
+  fn x_from_slice(&mut self, s: &[f32]) {
+    self.base_mut().x_as_mut_slice()
+        .copy_from_slice(s);
   }
   // 状態共分散行列
-  pub fn P_set(&mut self, 
-               r: usize, c: usize, s: f32) 
+  fn P_set(&mut self, 
+           r: usize, c: usize, s: f32) 
   {
-    match self {
-      $(Self::$kf(f) => f.fb.P[(r, c)] = s,)*
-    }
+    let m = self.base().x_as_slice().len();
+    self.base_mut().P_as_mut_slice()
+        [c * m + r] = s;
   }
-  pub fn P_from_slice(&mut self, s: &[f32])
-  {
-    match self {
-      $(Self::$kf(f) => 
-                 f.fb.P.copy_from_slice(s),)*
-    }
+  fn P_from_slice(&mut self, s: &[f32]) {
+    self.base_mut().P_as_mut_slice()
+        .copy_from_slice(s);
+  }
+  // 状態遷移行列
+  fn F_from_slice(&mut self, s: &[f32]) {
+    self.base_mut().F_as_mut_slice()
+        .copy_from_slice(s);
+  }
+  // プロセスノイズ行列
+  fn Q_from_slice(&mut self, s: &[f32]) {
+    self.base_mut().Q_as_mut_slice()
+        .copy_from_slice(s);
   }
+  // 移動量(dt)
+  // dtを持つフィルタ(UKF,EKF等)で上書きする
+  fn dt_set(&mut self, _dt: f32) {}
   // 制御入力
-  pub fn u_set(&mut self, r: usize, s: f32) {
-    match self {
-      $(Self::$kf(f) => f.fb.u[(r, 0)] = s,)*
-    }
+  fn u_set(&mut self, r: usize, s: f32) {
+    self.base_mut().u_as_mut_slice()[r] = s;
   }
   // 観測値
-  pub fn z_set(&mut self, r: usize, s: f32) {
-    match self {
-      $(Self::$kf(f) => f.fb.z[(r, 0)] = s,)*
-    }
+  fn z_set(&mut self, r: usize, s: f32) {
+    self.base_mut().z_as_mut_slice()[r] = s;
   }
   // 累積尤度値
-  pub fn cum_lh_set(&mut self, lh: f32) {
-    match self {
-      $(Self::$kf(f) => 
-         f.fb.cum_lh = Some(lh),)*
-    }
+  fn cum_lh_set(&mut self, lh: f32) {
+    self.base_mut().cum_lh_set(lh);
+  }
+  // 累積対数尤度値
+  fn cum_log_lh_set(&mut self, llh: f32) {
+    self.base_mut().cum_log_lh_set(llh);
   }
 //-- 値取得 --
   // 状態変数
-  pub fn x(&self, r: usize) -> f32 {
-    match self {
-      $(Self::$kf(f) => f.fb.x[(r, 0)],)*
-    }
+  fn x(&self, r: usize) -> f32 {
+    self.base().x_as_slice()[r]
   }
   // 状態共分散行列
-  pub fn P(&self, r: usize, c: usize) -> f32 {
-    match self {
-      $(Self::$kf(f) => f.fb.P[(r, c)],)*
-    }
+  fn P(&self, r: usize, c: usize) -> f32 {
+    let m = self.base().x_as_slice().len();
+    self.base().P_as_slice()[c * m + r]
   }
   // 残差
-  pub fn y(&self, r: usize) -> f32 {
-    match self {
-      $(Self::$kf(f) => f.fb.y[(r, 0)],)*
-    }
+  fn y(&self, r: usize) -> f32 {
+    self.base().y_as_slice()[r]
   }
   // 観測ノイズ行列
-  pub fn R(&self, r: usize, c: usize) -> f32 {
-    match self {
-      $(Self::$kf(f) => f.fb.R[(r, c)],)*
-    }
+  fn R(&self, r: usize, c: usize) -> f32 {
+    let n = self.base().y_as_slice().len();
+    self.base().R_as_slice()[c * n + r]
   }
   // 発展共分散行列
-  pub fn S(&self, r: usize, c: usize) -> f32 {
-    match self {
-      $(Self::$kf(f) => f.fb.S[(r, c)],)*
-    }
+  fn S(&self, r: usize, c: usize) -> f32 {
+    let n = self.base().y_as_slice().len();
+    self.base().S_as_slice()[c * n + r]
   }
   // 正規化された残差 y.T*S^-1*y
-  pub fn y_eps(&self) -> f32 {
-    match self {
-      $(Self::$kf(f) => f.fb.y_eps(),)*
-    }
+  fn y_eps(&self) -> f32 {
+    self.base().y_eps()
   }
   // 尤度
-  pub fn likelihood(&self) -> f32 {
-    match self {
-      $(Self::$kf(f) => f.fb.likelihood(),)*
-    }
+  fn likelihood(&self) -> f32 {
+    self.base().likelihood()
   }
   // 対数尤度
-  pub fn log_likelihood(&self) -> f32 {
-    match self {
-      $(Self::$kf(f) => 
-                 f.fb.log_likelihood(),)*
-    }
+  fn log_likelihood(&self) -> f32 {
+    self.base().log_likelihood()
   }
   // 累積尤度値
-  pub fn cum_lh(&self) -> f32 {
-    match self {
-      $(Self::$kf(f) => f.fb.cum_lh(),)*
-    }
+  fn cum_lh(&self) -> f32 {
+    self.base().cum_lh()
+  }
+  // 累積対数尤度値
+  fn cum_log_lh(&self) -> f32 {
+    self.base().cum_log_lh()
+  }
+  // 観測ゲート判定結果
+  fn gate_state(&self) -> GateState {
+    self.base().gate_state()
   }
-  //-- スライス取得 --
+//-- スライス取得 --
   // 状態変数
-  pub fn x_as_slice(&self) -> &[f32] {
-    match self {
-      $(Self::$kf(f) => f.fb.x.as_slice(),)*
-    }
+  fn x_as_slice(&self) -> &[f32] {
+    self.base().x_as_slice()
   }
   // 状態共分散行列
-  pub fn P_as_slice(&self) -> &[f32] {
-    match self {
-      $(Self::$kf(f) => f.fb.P.as_slice(),)*
+  fn P_as_slice(&self) -> &[f32] {
+    self.base().P_as_slice()
+  }
+//-- スナップショット --
+  // 保存(戻り値:書込んだバイト数)
+  fn save(&self, buf: &mut [u8])
+    -> Result<usize, SnapshotError>
+  {
+    self.base().save(buf)
+  }
+  // 復元(戻り値:読込んだバイト数)
+  fn restore(&mut self, buf: &[u8])
+    -> Result<usize, SnapshotError>
+  {
+    self.base_mut().restore(buf)
+  }
+}
+// フィルタ供給enum用Filter実装マクロ =========
+// enumの各バリアントが保持するフィルタに
+// Filterトレイトの実装を委譲する。
+//   filter_supply_impl!(Filters: Kf1, Kf2,);
+#[macro_export]
+macro_rules! filter_supply_impl 
+{
+ ($en:ident: $($kf:ident,)*) => {
+  impl $crate::Filter for $en {
+    fn step_predict(&mut self) 
+      -> Result<(), $crate::FilterError> 
+    {
+      match self {
+        $(Self::$kf(f) => f.step_predict(),)*
+      }
+    }
+    fn step_update(&mut self) 
+      -> Result<(), $crate::FilterError> 
+    {
+      match self {
+        $(Self::$kf(f) => f.step_update(),)*
+      }
+    }
+    fn base(&self) 
+      -> &dyn $crate::FilterState 
+    {
+      match self {
+        $(Self::$kf(f) => f.base(),)*
+      }
+    }
+    fn base_mut(&mut self) 
+      -> &mut dyn $crate::FilterState 
+    {
+      match self {
+        $(Self::$kf(f) => f.base_mut(),)*
+      }
+    }
+    fn dt_set(&mut self, dt: f32) {
+      match self {
+        $(Self::$kf(f) => f.dt_set(dt),)*
+      }
     }
   }
  }
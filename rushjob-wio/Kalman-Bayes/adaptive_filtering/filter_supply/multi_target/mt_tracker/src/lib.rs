@@ -0,0 +1,792 @@
+#![no_std]
+// 複数目標追跡(トラック管理)
+//
+// KalmanFilterのトラックを最大T個保持し、
+// 観測値リスト(最大Z個)を毎ステップ
+// トラックに割当てて更新する。
+//
+// # 1ステップの処理 step(zs)
+//  1. 全トラックを予測
+//  2. コスト行列作成
+//       d² = y.T @ inv(S) @ y
+//       (y = z - Hx, S = HPH' + R)
+//     d² > gate(χ²閾値)はゲート外
+//  3. 割当
+//       NearestNeighbour: d²の小さい組から
+//                         貪欲に割当
+//       Global          : ゲート内の割当数が
+//                         最大で、d²の合計が最小と
+//                         なる割当(ハンガリアン法)
+//       Jpda            : 割当を確率で扱う
+//                         (下記JPDA参照)
+//  4. 割当てたトラックを更新
+//  5. 割当のない観測値から仮トラック生成
+//  6. 確定/削除判定
+//       仮トラック: 直近n回中m回以上割当で確定
+//                   n回経過で未確定なら削除
+//       確定トラック: 連続delete_misses回
+//                     割当なしで削除
+//
+// # JPDA(結合確率的データ割当)
+//  ゲート内の観測値 j の尤度比
+//    L[t][j] = pd * N(y; 0, S) / λ
+//    (N(y; 0, S)はFilterBase::likelihood)
+//  トラックと観測値の組合せ(結合事象)ごとに
+//    Π L[t][j] (割当あり)
+//    Π (1 - pd*pg) (割当なし)
+//  を求めて正規化し、割当確率β[t][j]と
+//  割当なし確率β0[t]を算出する。
+//  更新は全観測値の残差をβで合成する
+//    y  = Σ β[j] * y[j]
+//    x += K @ y
+//    P  = β0 * P + (1 - β0) * (P - K S K.T)
+//       + K (Σ β[j] y[j] y[j].T - y y.T) K.T
+//  (最後の項が残差のばらつき分)
+//  pd: 検出確率, pg: ゲート確率
+//  λ : クラッタ密度(観測空間の単位体積当り)
+//  ※結合事象は全列挙のため、同じゲートに
+//    入るトラック・観測値が多いと重くなる。
+//    JPDAは確定トラックのみに適用し、
+//    仮トラックは最近傍で1対1に割当てる。
+//  ゲート内に観測値があれば割当ありとして
+//  確定/削除判定し、どのゲートにも入らない
+//  観測値から仮トラックを生成する。
+//
+// 新規トラックはtemplate(F,Q,H,R,P)を複製し
+// 状態xをinit_fn(z, H)で作成する。
+// デフォルトは x = H.T @ z (Hが観測する状態を
+// 選び出す行列の場合に使える)。
+// -----------------------------------------
+use filter_base::*;
+use mv_kalmanfilter::KalmanFilter;
+use nalgebra::{SMatrix, DimMin, Const};
+
+// ゲート外コスト(割当不可の目印)
+// ハンガリアン法には渡さない
+const GATE_OUT: f32 = 1.0e9;
+
+// 割当方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Association {
+  // ゲート付き最近傍(貪欲法)
+  NearestNeighbour,
+  // 大域最近傍(ハンガリアン法)
+  Global,
+  // 結合確率的データ割当
+  Jpda,
+}
+// トラック状態
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackStatus {
+  Tentative, // 仮
+  Confirmed, // 確定
+}
+// トラック
+#[derive(Clone, Debug)]
+pub struct Track<const M: usize,
+                 const N: usize,
+                 const C: usize>
+{
+  pub kf    : KalmanFilter<M, N, C>,
+  pub id    : u32,         // 識別番号
+  pub status: TrackStatus,
+  pub age   : usize,       // 生成後ステップ数
+  pub misses: usize,       // 連続割当なし回数
+  hist      : u32,         // 割当履歴(bit0:最新)
+}
+impl<const M: usize,
+     const N: usize,
+     const C: usize>
+    Track<M, N, C>
+{
+  // 直近n回中の割当回数
+  pub fn hits(&self, n: usize) -> u32 {
+    let mask = if n >= 32 { u32::MAX }
+               else { (1u32 << n) - 1 };
+    (self.hist & mask).count_ones()
+  }
+  // 今回割当があったか
+  pub fn is_hit(&self) -> bool {
+    self.hist & 1 == 1
+  }
+  // 割当結果記録
+  fn record(&mut self, hit: bool) {
+    self.hist = (self.hist << 1) | hit as u32;
+    self.age += 1;
+    self.misses = if hit { 0 }
+                  else { self.misses + 1 };
+  }
+}
+
+#[allow(non_snake_case)]
+pub struct TrackManager<const M: usize,
+                        const N: usize,
+                        const C: usize,
+                        const T: usize, // 最大トラック数
+                        const Z: usize> // 最大観測数
+{
+  // トラック(Noneは空き)
+  pub tracks: [Option<Track<M, N, C>>; T],
+  // 新規トラックのひな形
+  pub template: KalmanFilter<M, N, C>,
+  // 新規トラックの状態作成関数
+  pub init_fn : fn(&SMatrix<f32, N, 1>, // z
+                   &SMatrix<f32, N, M>, // H
+                ) -> SMatrix<f32, M, 1>,
+  // 割当方式
+  pub association: Association,
+  // ゲート閾値(χ²)
+  pub gate: f32,
+  // JPDA用
+  pub pd     : f32, // 検出確率
+  pub pg     : f32, // ゲート確率
+  pub clutter: f32, // クラッタ密度λ
+  // 確定条件:直近confirm_n回中confirm_m回
+  pub confirm_m: u32,
+  pub confirm_n: usize,
+  // 削除条件:連続割当なし回数
+  pub delete_misses: usize,
+  // 今回の割当結果(トラック→観測値)
+  pub assign: [Option<usize>; T],
+  // 次の識別番号
+  next_id: u32,
+}
+#[allow(non_snake_case)]
+impl<const M: usize,
+     const N: usize,
+     const C: usize,
+     const T: usize,
+     const Z: usize>
+    TrackManager<M, N, C, T, Z>
+where
+    Const<N>: DimMin<Const<N>,
+                     Output = Const<N>>,
+{
+  pub fn new(template: KalmanFilter<M, N, C>)
+    -> Self
+  {
+    Self {
+      tracks  : core::array::from_fn(|_| None),
+      template,
+      init_fn : init_fn_default,
+      association: Association::Global,
+      gate    : chi2_threshold(N, 0.99),
+      pd      : 0.9,
+      pg      : 0.99,
+      clutter : 1.0e-3,
+      confirm_m: 2,
+      confirm_n: 3,
+      delete_misses: 3,
+      assign  : [None; T],
+      next_id : 0,
+    }
+  }
+  // ゲート設定(信頼度)
+  pub fn gate_set(&mut self, conf: f32) {
+    self.gate = chi2_threshold(N, conf);
+    self.pg   = conf;
+  }
+  // 1ステップ処理
+  // 数値エラーになったトラックは削除する
+  pub fn step(&mut self,
+              zs: &[SMatrix<f32, N, 1>])
+  {
+    assert!(zs.len() <= Z,
+            // 観測数がZ以下じゃないとダメ
+            "zs.len() <= Z, otherwise.");
+    assert!(self.confirm_n <= 32,
+            // 履歴は32回まで
+            "confirm_n <= 32, otherwise.");
+    // 1. 予測
+    self.tracks.iter_mut().for_each(|t|
+      if let Some(tr) = t {
+        if Filter::try_predict(&mut tr.kf)
+           .is_err() {
+          *t = None;
+        }
+      }
+    );
+    // 2.～4. 割当と更新
+    let mut used = [false; Z];
+    if self.association == Association::Jpda {
+      self.jpda_update(zs, &mut used);
+    } else {
+      self.hard_update(zs, &mut used);
+    }
+    // 6. 確定/削除判定(新規生成前に実施)
+    let (m, n, d) = (self.confirm_m,
+                     self.confirm_n,
+                     self.delete_misses);
+    self.tracks.iter_mut().for_each(|t| {
+      let Some(tr) = t else { return };
+      match tr.status {
+        TrackStatus::Tentative => {
+          if tr.hits(n) >= m {
+            tr.status = TrackStatus::Confirmed;
+          } else if tr.age >= n {
+            *t = None;
+          }
+        }
+        TrackStatus::Confirmed => {
+          if tr.misses >= d {
+            *t = None;
+          }
+        }
+      }
+    });
+    // 5. 割当のない観測値から仮トラック生成
+    for (z, _) in zs.iter()
+                    .zip(used.iter())
+                    .filter(|(_, u)| !**u)
+    {
+      self.initiate(z);
+    }
+  }
+  // 割当(1対1)と更新
+  fn hard_update(&mut self,
+                 zs  : &[SMatrix<f32, N, 1>],
+                 used: &mut [bool; Z])
+  {
+    // 2. コスト行列
+    let cost = self.cost_matrix(zs);
+    // 3. 割当
+    self.assign = match self.association {
+      Association::NearestNeighbour =>
+        nearest_neighbour::<T, Z>(
+          &cost, zs.len()
+        ),
+      _ =>
+        global_nearest::<T, Z>(
+          &cost, zs.len(), self.gate
+        ),
+    };
+    // 4. 更新
+    for (t, a) in self.tracks.iter_mut()
+                      .zip(self.assign.iter())
+    {
+      if let Some(j) = a {
+        used[*j] = true;
+      }
+      Self::hard_step(t, a.map(|j| &zs[j]));
+    }
+  }
+  // 1対1割当の更新(割当なしはNone)
+  // 数値エラーならトラック削除
+  fn hard_step(t: &mut Option<Track<M, N, C>>,
+               z: Option<&SMatrix<f32, N, 1>>)
+  {
+    let Some(tr) = t else { return };
+    match z {
+      Some(z) => {
+        tr.kf.fb.z = *z;
+        if Filter::try_update(&mut tr.kf)
+           .is_err() {
+          *t = None;
+          return;
+        }
+        tr.record(true);
+      }
+      None => tr.record(false),
+    }
+  }
+  // JPDAによる割当確率算出と更新
+  // 確定トラックのみJPDAで更新し、
+  // 仮トラックは最近傍で1対1に割当てる
+  // assignには最も確率の高い観測値を設定
+  fn jpda_update(&mut self,
+                 zs  : &[SMatrix<f32, N, 1>],
+                 used: &mut [bool; Z])
+  {
+    // 2. 尤度比行列(確定トラック)と
+    //    コスト行列(仮トラック)
+    let lh = self.likelihood_matrix(zs);
+    let mut cost = self.cost_matrix(zs);
+    let mut active = [false; T];
+    for ((a, t), c) in active.iter_mut()
+                             .zip(self.tracks.iter())
+                             .zip(cost.iter_mut())
+    {
+      if t.as_ref().is_some_and(|tr|
+           tr.status == TrackStatus::Confirmed) {
+        *a = true;
+        *c = [GATE_OUT; Z];
+      }
+    }
+    // 3. 割当確率と仮トラックの割当
+    let (beta, beta0) = jpda_betas::<T, Z>(
+                          &lh, &active, zs.len(),
+                          self.pd, self.pg,
+                        );
+    self.assign = nearest_neighbour::<T, Z>(
+                    &cost, zs.len()
+                  );
+    // 4. 更新
+    for (i, t) in self.tracks.iter_mut()
+                      .enumerate()
+    {
+      if !active[i] {
+        if let Some(j) = self.assign[i] {
+          used[j] = true;
+        }
+        Self::hard_step(
+          t, self.assign[i].map(|j| &zs[j])
+        );
+        continue;
+      }
+      let Some(tr) = t else { continue };
+      let mut bmax = beta0[i];
+      let mut hit  = false;
+      for (j, u) in used.iter_mut()
+                        .enumerate()
+                        .take(zs.len()) {
+        if lh[i][j] > 0.0 {
+          *u  = true;
+          hit = true;
+        }
+        if beta[i][j] > bmax {
+          bmax = beta[i][j];
+          self.assign[i] = Some(j);
+        }
+      }
+      if hit &&
+         pda_update(&mut tr.kf, zs,
+                    &beta[i], beta0[i])
+         .is_err() {
+        *t = None;
+        continue;
+      }
+      tr.record(hit);
+    }
+  }
+  // 尤度比行列 pd * N(y; 0, S) / λ
+  // (ゲート外・空きは0)
+  pub fn likelihood_matrix(
+    &mut self,
+    zs: &[SMatrix<f32, N, 1>],
+  ) -> [[f32; Z]; T]
+  {
+    let mut lh = [[0.0; Z]; T];
+    let k = self.pd / self.clutter;
+    for (t, row) in self.tracks.iter_mut()
+                        .zip(lh.iter_mut())
+    {
+      let Some(tr) = t else { continue };
+      let fb = &mut tr.kf.fb;
+      // S = HPH' + R
+      fb.S = fb.H * fb.P * fb.H.transpose() +
+             fb.R;
+      let zp = fb.H * fb.x;
+      for (z, l) in zs.iter()
+                      .zip(row.iter_mut())
+      {
+        fb.y = z - zp;
+        match mahalanobis(&fb.y, &fb.S) {
+          Some(d2) if d2 <= self.gate =>
+            *l = fb.likelihood() * k,
+          _ => {}
+        }
+      }
+    }
+    lh
+  }
+  // 仮トラック生成(空きがなければ何もしない)
+  pub fn initiate(&mut self,
+                  z: &SMatrix<f32, N, 1>)
+    -> Option<u32>
+  {
+    let slot = self.tracks.iter_mut()
+                   .find(|t| t.is_none())?;
+    let mut kf = self.template.clone();
+    kf.fb.x = (self.init_fn)(z, &kf.fb.H);
+    let id = self.next_id;
+    self.next_id = self.next_id.wrapping_add(1);
+    *slot = Some(Track {
+      kf,
+      id,
+      status: TrackStatus::Tentative,
+      age   : 1,
+      misses: 0,
+      hist  : 1,
+    });
+    Some(id)
+  }
+  // 確定トラック
+  pub fn confirmed(&self)
+    -> impl Iterator<Item = &Track<M, N, C>>
+  {
+    self.tracks.iter()
+        .flatten()
+        .filter(|t|
+          t.status == TrackStatus::Confirmed
+        )
+  }
+  // トラック数
+  pub fn len(&self) -> usize {
+    self.tracks.iter().flatten().count()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+  // 全トラック削除
+  pub fn clear(&mut self) {
+    self.tracks.iter_mut()
+        .for_each(|t| *t = None);
+  }
+  // コスト行列 d² (ゲート外・空きはGATE_OUT)
+  pub fn cost_matrix(&self,
+                     zs: &[SMatrix<f32, N, 1>])
+    -> [[f32; Z]; T]
+  {
+    let mut cost = [[GATE_OUT; Z]; T];
+    for (t, row) in self.tracks.iter()
+                        .zip(cost.iter_mut())
+    {
+      let Some(tr) = t else { continue };
+      let fb = &tr.kf.fb;
+      // S = HPH' + R
+      let S  = fb.H * fb.P * fb.H.transpose() +
+               fb.R;
+      let zp = fb.H * fb.x;
+      for (z, c) in zs.iter()
+                      .zip(row.iter_mut())
+      {
+        match mahalanobis(&(z - zp), &S) {
+          Some(d2) if d2 <= self.gate =>
+            *c = d2,
+          _ => {}
+        }
+      }
+    }
+    cost
+  }
+}
+// マハラノビス距離の二乗 y.T @ inv(S) @ y
+// Sが正定値でなければNone
+#[allow(non_snake_case)]
+pub fn mahalanobis<const N: usize>(
+  y: &SMatrix<f32, N, 1>,
+  S: &SMatrix<f32, N, N>,
+) -> Option<f32>
+{
+  let ch = S.cholesky()?;
+  Some(*(y.transpose() * ch.solve(y))
+       .as_scalar())
+}
+// JPDA割当確率
+//   lh    : 尤度比(0はゲート外)
+//   active: 有効なトラック
+// 戻り値:(β[t][j], β0[t])
+pub fn jpda_betas<const T: usize,
+                  const Z: usize>(
+  lh    : &[[f32; Z]; T],
+  active: &[bool; T],
+  nz    : usize,
+  pd    : f32,
+  pg    : f32,
+) -> ([[f32; Z]; T], [f32; T])
+{
+  let mut je = JointEvents::<T, Z> {
+    lh, active, nz,
+    miss : 1.0 - pd * pg,
+    event: [None; T],
+    used : [false; Z],
+    beta : [[0.0; Z]; T],
+    beta0: [0.0; T],
+    total: 0.0,
+  };
+  je.enumerate(0, 1.0);
+  let (mut beta, mut beta0) = (je.beta,
+                               je.beta0);
+  if je.total > 0.0 {
+    beta.iter_mut().flatten()
+        .for_each(|b| *b /= je.total);
+    beta0.iter_mut()
+         .for_each(|b| *b /= je.total);
+  }
+  (beta, beta0)
+}
+// 結合事象の列挙
+struct JointEvents<'a,
+                   const T: usize,
+                   const Z: usize>
+{
+  lh    : &'a [[f32; Z]; T],
+  active: &'a [bool; T],
+  nz    : usize,
+  miss  : f32,                 // 1 - pd*pg
+  event : [Option<usize>; T],  // 列挙中の事象
+  used  : [bool; Z],
+  beta  : [[f32; Z]; T],
+  beta0 : [f32; T],
+  total : f32,
+}
+impl<const T: usize,
+     const Z: usize>
+    JointEvents<'_, T, Z>
+{
+  // トラックtの割当を決めて次へ
+  fn enumerate(&mut self, t: usize, w: f32) {
+    if t == T {
+      // 事象確定:重みを加算
+      self.total += w;
+      for k in 0..T {
+        match self.event[k] {
+          Some(j) => self.beta[k][j] += w,
+          None    => self.beta0[k]   += w,
+        }
+      }
+      return;
+    }
+    self.event[t] = None;
+    if !self.active[t] {
+      self.enumerate(t + 1, w);
+      return;
+    }
+    // 割当なし
+    self.enumerate(t + 1, w * self.miss);
+    // 観測値jを割当
+    for j in 0..self.nz {
+      let l = self.lh[t][j];
+      if self.used[j] || l <= 0.0 {continue}
+      self.used[j]  = true;
+      self.event[t] = Some(j);
+      self.enumerate(t + 1, w * l);
+      self.used[j]  = false;
+    }
+    self.event[t] = None;
+  }
+}
+// PDA合成更新
+//   beta : 観測値ごとの割当確率
+//   beta0: 割当なし確率
+#[allow(non_snake_case)]
+pub fn pda_update<const M: usize,
+                  const N: usize,
+                  const C: usize>(
+  kf   : &mut KalmanFilter<M, N, C>,
+  zs   : &[SMatrix<f32, N, 1>],
+  beta : &[f32],
+  beta0: f32,
+) -> Result<(), FilterError>
+where
+    Const<N>: DimMin<Const<N>,
+                     Output = Const<N>>,
+{
+  let fb = &mut kf.fb;
+  // S = HPH' + R, K = PH'inv(S)
+  let PHt = fb.P * fb.H.transpose();
+  let S   = fb.H * PHt + fb.R;
+  let K   = PHt *
+            S.try_inverse()
+             .ok_or(
+               FilterError::SingularInnovation
+             )?;
+  // 合成残差 y = Σβy, Σβyy'
+  let zp = fb.H * fb.x;
+  let mut y  = SMatrix::<f32, N, 1>::zeros();
+  let mut yy = SMatrix::<f32, N, N>::zeros();
+  for (z, b) in zs.iter().zip(beta.iter()) {
+    let yj = z - zp;
+    y  += yj * *b;
+    yy += (yj * yj.transpose()) * *b;
+  }
+  // x = x + Ky
+  fb.x += K * y;
+  // P = β0 P + (1-β0)(P - KSK')
+  //   + K(Σβyy' - yy')K'
+  let Pc = fb.P - K * S * K.transpose();
+  fb.P = fb.P * beta0 + Pc * (1.0 - beta0) +
+         K * (yy - y * y.transpose()) *
+         K.transpose();
+  fb.y = y;
+  fb.S = S;
+  fb.K = K;
+  fb.check_finite()?;
+  FilterState::after_update(fb);
+  Ok(())
+}
+// ゲート付き最近傍(貪欲法)
+// d²の小さい組から順に割当てる
+pub fn nearest_neighbour<const T: usize,
+                         const Z: usize>(
+  cost: &[[f32; Z]; T],
+  nz  : usize,
+) -> [Option<usize>; T]
+{
+  let mut assign = [None; T];
+  let mut used   = [false; Z];
+  loop {
+    let mut best: Option<(usize, usize, f32)>
+                  = None;
+    for (i, row) in cost.iter().enumerate() {
+      if assign[i].is_some() {continue}
+      for (j, &c) in row.iter()
+                        .enumerate()
+                        .take(nz) {
+        if used[j] || c >= GATE_OUT {continue}
+        if best.is_none_or(|(_, _, b)| c < b) {
+          best = Some((i, j, c));
+        }
+      }
+    }
+    let Some((i, j, _)) = best else { break };
+    assign[i] = Some(j);
+    used[j]   = true;
+  }
+  assign
+}
+// 大域最近傍(ハンガリアン法)
+// ゲート内の割当数が最大となる中で
+// d²合計が最小となる割当
+// ゲート内の観測値がない行(空きトラック含む)
+// は対象外とする。
+// ゲート外はbig = gate*(T+Z+1)として解く。
+// ゲート内だけの割当のd²合計は高々T*gate
+// なので、ゲート外を含む割当より必ず小さい。
+// (GATE_OUTのような巨大値を使うと
+//  ポテンシャル(f32)の精度が落ちて
+//  最適でない割当になる)
+pub fn global_nearest<const T: usize,
+                      const Z: usize>(
+  cost: &[[f32; Z]; T],
+  nz  : usize,
+  gate: f32,
+) -> [Option<usize>; T]
+{
+  let mut assign = [None; T];
+  // 対象行(トラック)
+  let mut rows = [0usize; T];
+  let mut nr   = 0;
+  for (i, row) in cost.iter().enumerate() {
+    if row.iter().take(nz).any(|&c| c < GATE_OUT) {
+      rows[nr] = i;
+      nr += 1;
+    }
+  }
+  let big = gate * (T + Z + 1) as f32;
+  let c = |i: usize, j: usize| {
+    let c = cost[rows[i]][j];
+    if c >= GATE_OUT { big } else { c }
+  };
+  if nr <= nz {
+    // トラック(行)→観測値(列)
+    let mut ra = [None; T];
+    hungarian::<T, Z>(nr, nz, c, &mut ra);
+    ra.iter().enumerate().take(nr)
+      .for_each(|(k, a)| assign[rows[k]] = *a);
+  } else {
+    // 観測値(行)→トラック(列)で解いて戻す
+    let mut za = [None; Z];
+    hungarian::<Z, T>(
+      nz, nr, |i, j| c(j, i), &mut za
+    );
+    za.iter().enumerate().take(nz)
+      .for_each(|(j, k)|
+        if let Some(k) = k {
+          assign[rows[*k]] = Some(j);
+        }
+      );
+  }
+  // ゲート外の割当は捨てる
+  assign.iter_mut().enumerate()
+        .for_each(|(i, a)|
+          if a.is_some_and(|j|
+               cost[i][j] >= GATE_OUT) {
+            *a = None;
+          }
+        );
+  assign
+}
+// 最小コスト割当(ハンガリアン法)
+// n行 x m列(n <= m)のコストで全行を割当て、
+// 行iの割当列をrow[i]に設定する。
+//   R >= n, K >= m
+// 仮想列(None)を起点に最短増加路を探し、
+// ポテンシャルu, vを更新していく O(n²m)
+pub fn hungarian<const R: usize,
+                 const K: usize>(
+  n   : usize,
+  m   : usize,
+  cost: impl Fn(usize, usize) -> f32,
+  row : &mut [Option<usize>; R],
+)
+{
+  assert!(n <= m && n <= R && m <= K,
+          // n <= m, n <= R, m <= K じゃないとダメ
+          "n <= m, n <= R, m <= K, otherwise.");
+  let mut u = [0.0f32; R];
+  let mut v = [0.0f32; K];
+  // 列jに割当てた行
+  let mut p  : [Option<usize>; K] = [None; K];
+  // 増加路の1つ前の列
+  let mut way: [Option<usize>; K] = [None; K];
+  for i in 0..n {
+    let mut minv = [f32::INFINITY; K];
+    let mut used = [false; K];
+    let mut j0: Option<usize> = None;
+    loop {
+      // j0に割当てた行(仮想列は行i)
+      let i0 = match j0 {
+        None    => i,
+        Some(j) => {
+          used[j] = true;
+          p[j].unwrap()
+        }
+      };
+      let mut delta = f32::INFINITY;
+      let mut j1 = 0;
+      for j in 0..m {
+        if used[j] {continue}
+        let cur = cost(i0, j) - u[i0] - v[j];
+        if cur < minv[j] {
+          minv[j] = cur;
+          way[j]  = j0;
+        }
+        if minv[j] < delta {
+          delta = minv[j];
+          j1    = j;
+        }
+      }
+      // ポテンシャル更新
+      u[i] += delta;
+      for j in 0..m {
+        if used[j] {
+          u[p[j].unwrap()] += delta;
+          v[j] -= delta;
+        } else {
+          minv[j] -= delta;
+        }
+      }
+      j0 = Some(j1);
+      if p[j1].is_none() {break}
+    }
+    // 増加路を逆に辿って割当を入替え
+    while let Some(j) = j0 {
+      let jp = way[j];
+      p[j] = match jp {
+        None    => Some(i),
+        Some(k) => p[k],
+      };
+      j0 = jp;
+    }
+  }
+  row.fill(None);
+  p.iter().enumerate().take(m)
+   .for_each(|(j, i)|
+     if let Some(i) = i {
+       row[*i] = Some(j);
+     }
+   );
+}
+// --- 関数定義型 デフォルト実装 ---
+// 新規トラックの状態作成 x = H.T @ z
+#[allow(non_snake_case)]
+fn init_fn_default<const M: usize,
+                   const N: usize>(
+  z: &SMatrix<f32, N, 1>,
+  H: &SMatrix<f32, N, M>,
+) -> SMatrix<f32, M, 1>
+{
+  H.transpose() * z
+}
+// -------------------------------
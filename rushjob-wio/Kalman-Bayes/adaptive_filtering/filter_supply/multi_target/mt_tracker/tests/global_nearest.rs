@@ -0,0 +1,122 @@
+// global_nearestと総当りの比較
+// ゲート内の割当数が最大で、その中で
+// d²合計が最小となっていること
+use mt_tracker::global_nearest;
+
+// cost_matrixのゲート外と同じ値
+const OUT : f32 = 1.0e9;
+const GATE: f32 = 9.21;
+
+// 乱数(xorshift)
+struct Rng(u32);
+impl Rng {
+  fn f32(&mut self) -> f32 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 17;
+    self.0 ^= self.0 << 5;
+    (self.0 >> 8) as f32 / (1u32 << 24) as f32
+  }
+}
+// 総当り (割当数, d²合計)
+fn brute<const T: usize, const Z: usize>(
+  cost: &[[f32; Z]; T],
+  nz  : usize,
+  i   : usize,
+  used: &mut [bool; Z],
+) -> (usize, f32)
+{
+  if i == T {
+    return (0, 0.0);
+  }
+  // 割当なし
+  let mut best = brute(cost, nz, i + 1, used);
+  for j in 0..nz {
+    if used[j] || cost[i][j] >= OUT {continue}
+    used[j] = true;
+    let (n, s) = brute(cost, nz, i + 1, used);
+    used[j] = false;
+    let (n, s) = (n + 1, s + cost[i][j]);
+    if n > best.0 || (n == best.0 && s < best.1) {
+      best = (n, s);
+    }
+  }
+  best
+}
+// global_nearestの (割当数, d²合計)
+fn score<const T: usize, const Z: usize>(
+  cost  : &[[f32; Z]; T],
+  assign: &[Option<usize>; T],
+) -> (usize, f32)
+{
+  let mut used = [false; Z];
+  assign.iter().enumerate()
+    .filter_map(|(i, a)| a.map(|j| (i, j)))
+    .fold((0, 0.0), |(n, s), (i, j)| {
+      assert!(cost[i][j] < OUT, "gated out");
+      assert!(!used[j], "column used twice");
+      used[j] = true;
+      (n + 1, s + cost[i][j])
+    })
+}
+fn check<const T: usize, const Z: usize>(
+  rng: &mut Rng,
+  nz : usize,
+  cnt: usize,
+)
+{
+  for _ in 0..cnt {
+    let mut cost = [[OUT; Z]; T];
+    for row in cost.iter_mut() {
+      // 空きトラック
+      if rng.f32() < 0.1 {continue}
+      for c in row.iter_mut().take(nz) {
+        let d2 = rng.f32() * 2.0 * GATE;
+        if d2 <= GATE {
+          *c = d2;
+        }
+      }
+    }
+    let assign = global_nearest::<T, Z>(
+                   &cost, nz, GATE
+                 );
+    let (n, s) = score(&cost, &assign);
+    let (bn, bs) = brute(&cost, nz, 0,
+                         &mut [false; Z]);
+    assert_eq!(n, bn, "{cost:?}");
+    assert!((s - bs).abs() <= 1.0e-4 * (1.0 + bs),
+            "{s} != {bs}: {cost:?}");
+  }
+}
+
+// 安い部分割当(行1→列2 1.4)より割当数を優先
+// 期待値:行1→列3, 行2→列2, 行3→列1
+//        (行0は割当なし) 合計6.34
+#[test]
+fn max_cardinality_beats_cheaper_partial_assignment() {
+  let cost = [[OUT, OUT, 7.45, OUT],
+              [OUT, 1.71, 1.4, 1.22],
+              [OUT, 7.83, 2.72, OUT],
+              [OUT, 2.4, OUT, OUT]];
+  let assign = global_nearest::<4, 4>(
+                 &cost, 4, GATE
+               );
+  let (n, s) = score(&cost, &assign);
+  assert_eq!(n, 3);
+  assert!((s - 6.34).abs() < 1.0e-4, "{s}");
+}
+#[test]
+fn square() {
+  check::<4, 4>(&mut Rng(1), 4, 20000);
+}
+#[test]
+fn more_measurements() {
+  check::<3, 5>(&mut Rng(2), 5, 5000);
+}
+#[test]
+fn more_tracks() {
+  check::<5, 3>(&mut Rng(3), 3, 5000);
+}
+#[test]
+fn partial_measurements() {
+  check::<4, 6>(&mut Rng(4), 3, 5000);
+}
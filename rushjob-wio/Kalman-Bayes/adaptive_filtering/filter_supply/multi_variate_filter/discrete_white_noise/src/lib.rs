@@ -1,56 +1,75 @@
 // discrete_white_noise
 //
-// ブロック分散はこれでいいとして、
-// block_diagをどうするか。
-// nalgebraで類似関数を見つけられなかった。
-// 自作する？
+// プロセスノイズ行列Qの作成
 //
-// 自作案
-//   型指定
-//     型M:返却する行列次元,
-//     型B:ブロック単位の次元
-//   型間のルール
-//     B in (2, 3, 4) であること
-//     M >= B         であること
-//     M % B == 0     であること
-//     M == Bの場合
-//       ブロック単位をそのまま返却
-//     M > B の場合
-//       M / B回 対角にブロックを設定
+// # ノイズブロック(B x B, B in 2..=4)
+//  DiscreteWhiteNoise(PiecewiseWhiteNoise)
+//    区分的白色ノイズ(離散)
+//    var:加速度等の分散
+//  ContinuousWhiteNoise
+//    連続白色ノイズを離散化
+//    var:スペクトル密度
 //
-//   手順
-//     new
-//       M, Bを受取り、MxMとBxBの行列を
-//       ゼロ初期化で作成。
-//     トレイト
-//       型指定:M,Bを受取りMxMの行列を返却
-//     実装
-//       (b,_)=ブロック.shape()
-//       (q,_)=Q.shape()
-//       for i in 0..(q / b)
-//         Q.fixed_view::<B, B>(i*b, i*b)
-//         .copy_from(&ブロック)
-//
-//  自作関数は色々考えないといけず、
-//  めんどくさいので暫くは以下の方法
-//  でしのぐ。※利用者側で行う。
-//  let (qd, bd) = (M, B);
-//  (0..qd/bd).for_each(|i| 
-//    kf.Q.view_mut((i*bd, i*bd), (bd, bd))
-//      .copy_from(&bn)
-//  );
-
+// # ブロック対角 block_diag::<M, B>
+//  B x BのブロックをM/B回対角に並べた
+//  M x Mの行列を返却する。
+//  M % B != 0 はコンパイルエラー。
+//   例:2次元の定常加速度(x, vx, ax, y, vy, ay)
+//    kf.fb.Q = block_diag::<6, 3>(
+//      &DiscreteWhiteNoise::noise_block(dt, var)
+//    );
+//  noise_diag::<NB, M, B>(dt, var)は
+//  上記をまとめて行う。
+//    kf.fb.Q = noise_diag::<
+//                DiscreteWhiteNoise, 6, 3
+//              >(dt, var);
+// -----------------------------------------
 #![no_std]
 use nalgebra::SMatrix;
+#[allow(unused_imports)]
 use micromath::F32Ext;
 
+// 区分的白色ノイズ
 pub struct DiscreteWhiteNoise;
+pub type PiecewiseWhiteNoise = DiscreteWhiteNoise;
+// 連続白色ノイズ
+pub struct ContinuousWhiteNoise;
 
 pub trait Noizeblock<const B: usize> 
 {
   fn noise_block(dt: f32, var: f32)
     -> SMatrix::<f32, B, B>;
 }
+// ブロック対角行列作成
+// bnをM/B回対角に設定(他は0)
+pub fn block_diag<const M: usize,
+                  const B: usize>(
+  bn: &SMatrix<f32, B, B>,
+) -> SMatrix<f32, M, M>
+{
+  // 次元数チェック(コンパイル時)
+  const {
+    assert!(B > 0 && M.is_multiple_of(B),
+            // M % B == 0 じゃないとダメ
+            "M % B == 0, otherwise.");
+  }
+  let mut q = SMatrix::<f32, M, M>::zeros();
+  (0..M/B).for_each(|i|
+    q.fixed_view_mut::<B, B>(i*B, i*B)
+     .copy_from(bn)
+  );
+  q
+}
+// ノイズブロックからブロック対角行列作成
+pub fn noise_diag<NB: Noizeblock<B>,
+                  const M: usize,
+                  const B: usize>(
+  dt : f32,
+  var: f32,
+) -> SMatrix<f32, M, M>
+{
+  block_diag::<M, B>(&NB::noise_block(dt, var))
+}
 
 // 2 x 2
 impl Noizeblock<2> for DiscreteWhiteNoise
@@ -127,3 +146,82 @@ impl Noizeblock<4> for DiscreteWhiteNoise
 //  [(dt**3)/6,  (dt**2)/2 ,  dt,        
 //                                     1.]]
 
+// 連続白色ノイズ ==========================
+// 2 x 2
+impl Noizeblock<2> for ContinuousWhiteNoise
+{
+  fn noise_block(dt : f32, var: f32)
+    -> SMatrix::<f32, 2, 2>
+  {
+    let mut q =  SMatrix::<f32, 2, 2>::new(
+      dt.powi(3)/3., dt.powi(2)/2.,
+      dt.powi(2)/2., dt,
+    );
+    q *= var;
+    q
+  }
+}
+// [[(dt**3)/3, (dt**2)/2],
+//  [(dt**2)/2,        dt]]
+
+// 3 x 3
+impl Noizeblock<3> for ContinuousWhiteNoise
+{
+  fn noise_block(dt : f32, var: f32)
+    -> SMatrix::<f32, 3, 3>
+  {
+    let mut q =  SMatrix::<f32, 3, 3>::new(
+      dt.powi(5)/20.,
+              dt.powi(4)/8.,
+                      dt.powi(3)/6.,
+      dt.powi(4)/8.,
+              dt.powi(3)/3.,
+                      dt.powi(2)/2.,
+      dt.powi(3)/6.,
+              dt.powi(2)/2.,
+                      dt,
+    );
+    q *= var;
+    q
+  }
+}
+// [[(dt**5)/20, (dt**4)/8, (dt**3)/6],
+//  [(dt**4)/8,  (dt**3)/3, (dt**2)/2],
+//  [(dt**3)/6,  (dt**2)/2,        dt]]
+
+// 4 x 4
+impl Noizeblock<4> for ContinuousWhiteNoise
+{
+  fn noise_block(dt : f32, var: f32)
+    -> SMatrix::<f32, 4, 4>
+  {
+    let mut q =  SMatrix::<f32, 4, 4>::new(
+      dt.powi(7)/252.,
+              dt.powi(6)/72.,
+                      dt.powi(5)/30.,
+                              dt.powi(4)/24.,
+      dt.powi(6)/72.,
+              dt.powi(5)/20.,
+                      dt.powi(4)/8.,
+                              dt.powi(3)/6.,
+      dt.powi(5)/30.,
+              dt.powi(4)/8.,
+                      dt.powi(3)/3.,
+                              dt.powi(2)/2.,
+      dt.powi(4)/24.,
+              dt.powi(3)/6.,
+                      dt.powi(2)/2.,
+                              dt,
+    );
+    q *= var;
+    q
+  }
+}
+// [[(dt**7)/252, (dt**6)/72, (dt**5)/30,
+//                               (dt**4)/24],
+//  [(dt**6)/72,  (dt**5)/20, (dt**4)/8,
+//                               (dt**3)/6],
+//  [(dt**5)/30,  (dt**4)/8,  (dt**3)/3,
+//                               (dt**2)/2],
+//  [(dt**4)/24,  (dt**3)/6,  (dt**2)/2,
+//                                      dt]]
@@ -0,0 +1,283 @@
+#![no_std]
+// 情報フィルタ(Information Filter)
+//
+// # 予測 (KalmanFilterと同じ共分散形式)
+//    x = F @ x + B @ u
+//    P = F @ P @ F.T + Q
+//
+// # 更新 (情報形式)
+//    Y  = inv(P)             情報行列
+//    yv = Y @ x              情報ベクトル
+//    Y  += Σ H[j].T @ inv(R[j]) @ H[j]
+//    yv += Σ H[j].T @ inv(R[j]) @ z[j]
+//    P  = inv(Y)
+//    x  = P @ yv
+//
+//  観測は情報行列/ベクトルへの足し算なので
+//  観測周期や次元の異なる複数センサの観測を
+//  1回の更新でまとめて融合できる。
+//  (順番に依存せず、逐次更新と結果は同じ)
+//
+// # 使い方
+//  単一センサ: fb.z, fb.R, fb.Hを設定して
+//              predict(), update()
+//              (KalmanFilterと同じ)
+//  複数センサ(同じ観測次元):
+//    update_multi(&[Measurement, ...])
+//  複数センサ(観測次元が異なる):
+//    try_begin()
+//    try_fuse(&Measurement<M, 2>) ...
+//    try_fuse(&Measurement<M, 1>) ...
+//    try_finish()
+//  ※複数センサの更新は単一の残差y,
+//    残差共分散Sを持たないので、観測ゲート、
+//    尤度、NIS、ノイズ調整は使えない。
+//    gate_stateはUncheckedとなり、fb.y, fb.S
+//    は更新しない(以前の値のまま)。
+//
+//  Y : 情報行列          m x m
+//  yv: 情報ベクトル      m x 1
+//  ※Y, yvは最後の更新時点の値。
+//    状態はfb.x, fb.Pが正で、IMM等で外部から
+//    書き換えても次の更新で再計算される。
+//  ※無情報(Y = 0)からの開始は扱わない。
+//    fb.Pは有限値で初期化すること。
+// -----------------------------------------
+use filter_base::*;
+use mv_kalmanfilter::KalmanFilter;
+use nalgebra::{SMatrix, DimMin, Const};
+
+// 観測値(センサ1台分)
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct Measurement<const M: usize,
+                       const K: usize>
+{
+  pub H: SMatrix<f32, K, M>, // 観測関数
+  pub R: SMatrix<f32, K, K>, // 観測ノイズ
+  pub z: SMatrix<f32, K, 1>, // 観測値
+}
+#[allow(non_snake_case)]
+impl<const M: usize,
+     const K: usize>
+    Measurement<M, K>
+{
+  pub fn new(
+    H: SMatrix<f32, K, M>,
+    R: SMatrix<f32, K, K>,
+    z: SMatrix<f32, K, 1>,
+  ) -> Self {
+    Self { H, R, z }
+  }
+}
+
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct InformationFilter<const M: usize,
+                             const N: usize,
+                             const C: usize>
+{ // フィルタ共通変数定義
+  pub fb: FilterBase<M, N, C>,
+  // 情報形式
+  pub Y : SMatrix<f32, M, M>, // 情報行列
+  pub yv: SMatrix<f32, M, 1>, // 情報ベクトル
+}
+#[allow(non_snake_case)]
+impl <const M: usize,
+      const N: usize,
+      const C: usize>
+     InformationFilter<M, N, C>
+{
+  pub fn new() -> Self {
+    Self {
+      fb: FilterBase::<M, N, C>::new(),
+      Y : SMatrix::<f32, M, M>::identity(),
+      yv: SMatrix::<f32, M, 1>::zeros(),
+    }
+  }
+  // KalmanFilterから作成
+  pub fn from_kf(kf: &KalmanFilter<M, N, C>)
+    -> Self
+  {
+    let mut inf = Self::new();
+    inf.fb = kf.fb.clone();
+    inf
+  }
+  // KalmanFilterに変換
+  pub fn to_kf(&self) -> KalmanFilter<M, N, C> {
+    let mut kf = KalmanFilter::<M, N, C>::new();
+    kf.fb = self.fb.clone();
+    kf
+  }
+  // 予測
+  pub fn predict(&mut self) {
+    self.try_predict().unwrap();
+  }
+  pub fn try_predict(&mut self)
+    -> Result<(), FilterError>
+  {
+    // x = Fx + Bu
+    self.fb.x = self.fb.F * self.fb.x +
+                self.fb.B * self.fb.u;
+    // P = FPF' + Q
+    self.fb.P = (self.fb.F * self.fb.P) *
+                self.fb.F.transpose() +
+                self.fb.Q;
+    self.fb.check_finite()
+  }
+  // 更新(単一センサ:fb.z, fb.R, fb.H)
+  pub fn update(&mut self) {
+    self.try_update().unwrap();
+  }
+  pub fn try_update(&mut self)
+    -> Result<(), FilterError>
+  {
+    // 尤度計算用 y = z - Hx, S = HPH' + R
+    let PHt = self.fb.P *
+              self.fb.H.transpose();
+    self.fb.S = self.fb.H * PHt + self.fb.R;
+    self.fb.y = self.fb.z - self.fb.H * self.fb.x;
+    // 観測ゲート:ゲート外は棄却かR拡大
+    let Some(g) = self.fb.try_gate()? else {
+      return Ok(());
+    };
+    let R = self.fb.R * g;
+    let S = self.fb.H * PHt + R;
+    let Ri  = R.try_inverse()
+                  .ok_or(
+                    FilterError::SingularInnovation
+                  )?;
+    // Y = inv(P) + H'inv(R)H
+    // yv= inv(P)x + H'inv(R)z
+    self.try_begin()?;
+    let HtRi = self.fb.H.transpose() * Ri;
+    self.Y  += HtRi * self.fb.H;
+    self.yv += HtRi * self.fb.z;
+    self.try_finish()?;
+    // K = P+ H'inv(R)
+    self.fb.K = self.fb.P * HtRi;
+    self.fb.S = S;
+    Ok(())
+  }
+  // 更新(複数センサ:観測次元が同じ)
+  pub fn update_multi<const K: usize>(
+    &mut self,
+    ms: &[Measurement<M, K>],
+  ) {
+    self.try_update_multi(ms).unwrap();
+  }
+  // エラーの場合はY, yvを戻し、
+  // fb.x, fb.Pも変えずにエラー返却
+  // fb.y, fb.Sは更新しない(ゲートも適用
+  // しないのでgate_stateはUnchecked)
+  pub fn try_update_multi<const K: usize>(
+    &mut self,
+    ms: &[Measurement<M, K>],
+  ) -> Result<(), FilterError>
+  {
+    let (Y, yv) = (self.Y, self.yv);
+    let r = self.try_begin()
+                .and_then(|_|
+                  ms.iter()
+                    .try_for_each(|m| self.try_fuse(m))
+                )
+                .and_then(|_| self.try_finish());
+    if let Err(e) = r {
+      self.Y  = Y;
+      self.yv = yv;
+      return Err(e);
+    }
+    self.fb.gate_state = GateState::Unchecked;
+    Ok(())
+  }
+  // 情報形式への変換(融合開始)
+  // Y = inv(P), yv = Y @ x
+  pub fn try_begin(&mut self)
+    -> Result<(), FilterError>
+  {
+    self.Y  = self.fb.P.try_inverse()
+                  .ok_or(
+                    FilterError::SingularCovariance
+                  )?;
+    self.yv = self.Y * self.fb.x;
+    Ok(())
+  }
+  // 観測1件の融合
+  // Y  += H'inv(R)H, yv += H'inv(R)z
+  // Rが特異なら融合せずにエラー返却
+  // (それまでに融合した分はY, yvに残るので
+  //  やり直す場合はtry_beginから)
+  pub fn try_fuse<const K: usize>(
+    &mut self,
+    m: &Measurement<M, K>,
+  ) -> Result<(), FilterError>
+  {
+    let Ri = m.R.try_inverse()
+                .ok_or(
+                  FilterError::SingularInnovation
+                )?;
+    let HtRi = m.H.transpose() * Ri;
+    self.Y  += HtRi * m.H;
+    self.yv += HtRi * m.z;
+    Ok(())
+  }
+  // 共分散形式への変換(融合終了)
+  // P = inv(Y), x = P @ yv
+  // Yが特異か結果が有限値でなければ
+  // fb.x, fb.Pを変えずにエラー返却
+  pub fn try_finish(&mut self)
+    -> Result<(), FilterError>
+  {
+    let P = self.Y.try_inverse()
+                .ok_or(
+                  FilterError::SingularCovariance
+                )?;
+    let x = P * self.yv;
+    // 対称性を保つ
+    let P = (P + P.transpose()) * 0.5;
+    if !x.iter().chain(P.iter())
+         .all(|v| v.is_finite()) {
+      return Err(FilterError::NonFinite);
+    }
+    self.fb.x = x;
+    self.fb.P = P;
+    Ok(())
+  }
+}
+// フィルタ共通トレイト実装
+impl<const M: usize,
+     const N: usize,
+     const C: usize>
+    Filter for InformationFilter<M, N, C>
+where
+    Const<N>: DimMin<Const<N>,
+                     Output = Const<N>>,
+{
+  fn step_predict(&mut self)
+    -> Result<(), FilterError>
+  {
+    InformationFilter::try_predict(self)
+  }
+  fn step_update(&mut self)
+    -> Result<(), FilterError>
+  {
+    InformationFilter::try_update(self)
+  }
+  fn base(&self) -> &dyn FilterState {
+    &self.fb
+  }
+  fn base_mut(&mut self)
+    -> &mut dyn FilterState
+  {
+    &mut self.fb
+  }
+}
+// --- Clippy対応 ---
+impl<const M: usize,
+     const N: usize,
+     const C: usize>
+    Default for InformationFilter<M, N, C> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
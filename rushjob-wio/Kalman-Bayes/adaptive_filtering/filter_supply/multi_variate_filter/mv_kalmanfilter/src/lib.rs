@@ -49,6 +49,7 @@
 //     );
 // -----------------------------------------
 use filter_base::*;
+use nalgebra::{DimMin, Const};
 
 #[derive(Clone, Debug)]
 #[allow(non_snake_case)]
@@ -71,6 +72,11 @@ impl <const M: usize,
   }
   // 予測
   pub fn predict(&mut self) {
+    self.try_predict().unwrap();
+  }
+  pub fn try_predict(&mut self) 
+    -> Result<(), FilterError> 
+  {
     // x = Fx + Bu
     self.fb.x = self.fb.F * self.fb.x +
                 self.fb.B * self.fb.u;
@@ -78,9 +84,15 @@ impl <const M: usize,
     self.fb.P = (self.fb.F * self.fb.P) *
                 self.fb.F.transpose() +
                 self.fb.Q;
+    self.fb.check_finite()
   }
   // 更新
   pub fn update(&mut self) {
+    self.try_update().unwrap();
+  }
+  pub fn try_update(&mut self) 
+    -> Result<(), FilterError> 
+  {
     // y = z - Hx : 残差
     self.fb.y  = self.fb.z - 
                  self.fb.H * self.fb.x;
@@ -89,10 +101,21 @@ impl <const M: usize,
     let PHt = self.fb.P * 
               self.fb.H.transpose();
     self.fb.S  = self.fb.H * PHt + self.fb.R;
+    // 観測ゲート:ゲート外は棄却かR拡大
+    let Some(g) = self.fb.try_gate()? else {
+      return Ok(());
+    };
+    let R = self.fb.R * g;
+    if g != 1.0 {
+      self.fb.S = self.fb.H * PHt + R;
+    }
     // K = PH'inv(S)
+    // Sが特異なら状態を変えずにエラー返却
     self.fb.K  = PHt * 
                  self.fb.S.try_inverse()
-                          .unwrap();
+                          .ok_or(
+                   FilterError::SingularInnovation
+                 )?;
     // x = x + Ky
     self.fb.x  += self.fb.K * self.fb.y;
     // P = (I-KH)P は数値的に不安定なので
@@ -101,8 +124,37 @@ impl <const M: usize,
                self.fb.K * self.fb.H;
     self.fb.P   = (i_kh  * self.fb.P)  * 
                   i_kh.transpose()   + 
-                  (self.fb.K * self.fb.R) *
+                  (self.fb.K * R) *
                   self.fb.K.transpose();
+    self.fb.check_finite()
+  }
+}
+// フィルタ共通トレイト実装
+impl<const M: usize,
+     const N: usize,
+     const C: usize>
+    Filter for KalmanFilter<M, N, C>
+where
+    Const<N>: DimMin<Const<N>,
+                     Output = Const<N>>,
+{
+  fn step_predict(&mut self) 
+    -> Result<(), FilterError> 
+  {
+    KalmanFilter::try_predict(self)
+  }
+  fn step_update(&mut self) 
+    -> Result<(), FilterError> 
+  {
+    KalmanFilter::try_update(self)
+  }
+  fn base(&self) -> &dyn FilterState {
+    &self.fb
+  }
+  fn base_mut(&mut self) 
+    -> &mut dyn FilterState 
+  {
+    &mut self.fb
   }
 }
 // --- Clippy対応 ---
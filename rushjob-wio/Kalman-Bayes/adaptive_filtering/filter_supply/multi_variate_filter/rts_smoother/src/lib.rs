@@ -0,0 +1,193 @@
+#![no_std]
+// Rauch-Tung-Striebel平滑化
+//
+// # 後ろ向き計算 (k = n-2 → 0)
+//    Pp = F[k+1] @ P[k] @ F[k+1].T + Q[k+1]
+//    K  = P[k] @ F[k+1].T @ inv(Pp)
+//    x[k] += K @ (x[k+1] - F[k+1] @ x[k])
+//    P[k] += K @ (P[k+1] - Pp) @ K.T
+//
+//  x[k], P[k]: k時点のフィルタ結果(更新後)
+//  F[k], Q[k]: k時点の予測で使ったF,Q
+//              (x[k-1]からx[k]への遷移)
+//  制御入力B @ uは平滑化では考慮しない。
+//
+// 固定区間(rts_smoother)
+//   記録済みの履歴スライスをその場で平滑化
+//   する。バッチ処理(ホスト側)向け。
+// 固定遅れ(FixedLagSmoother)
+//   直近L個の履歴を保持し、新しい結果を
+//   追加する度にL-1ステップ前の平滑化値を
+//   算出する。デバイス上の逐次処理向け。
+// -----------------------------------------
+use nalgebra::SMatrix;
+use filter_base::*;
+
+// 固定区間平滑化
+// xs, Psにフィルタ結果を渡すと平滑化結果で
+// 上書きする。Fs, Qsはxsと同じ長さ。
+#[allow(non_snake_case)]
+pub fn rts_smoother<const M: usize>(
+  xs: &mut [SMatrix<f32, M, 1>],
+  Ps: &mut [SMatrix<f32, M, M>],
+  Fs: &[SMatrix<f32, M, M>],
+  Qs: &[SMatrix<f32, M, M>],
+) -> Result<(), FilterError>
+{
+  // 長さチェック
+  assert!(
+    xs.len() == Ps.len() &&
+    xs.len() == Fs.len() &&
+    xs.len() == Qs.len(),
+    // 履歴の長さが揃ってないとダメ
+    "xs, Ps, Fs, Qs length mismatch."
+  );
+  if xs.len() < 2 {
+    return Ok(());
+  }
+  for k in (0..xs.len()-1).rev() {
+    let (x, P) = rts_step(
+                   &xs[k],   &Ps[k],
+                   &xs[k+1], &Ps[k+1],
+                   &Fs[k+1], &Qs[k+1],
+                 )?;
+    xs[k] = x;
+    Ps[k] = P;
+  }
+  Ok(())
+}
+// 後ろ向き計算1ステップ
+// (x, P): k時点フィルタ結果
+// (xs, Ps): k+1時点平滑化結果
+#[allow(non_snake_case)]
+pub fn rts_step<const M: usize>(
+  x : &SMatrix<f32, M, 1>,
+  P : &SMatrix<f32, M, M>,
+  xs: &SMatrix<f32, M, 1>,
+  Ps: &SMatrix<f32, M, M>,
+  F : &SMatrix<f32, M, M>,
+  Q : &SMatrix<f32, M, M>,
+) -> Result<(SMatrix<f32, M, 1>,
+             SMatrix<f32, M, M>), FilterError>
+{
+  // 予測共分散
+  // Pp = FPF' + Q
+  let Pp = F * P * F.transpose() + Q;
+  // 平滑化ゲイン
+  // K = PF'inv(Pp)
+  let K  = P * F.transpose() *
+           Pp.try_inverse()
+             .ok_or(
+               FilterError::SingularCovariance
+             )?;
+  // x = x + K(xs - Fx)
+  let x = x + K * (xs - F * x);
+  // P = P + K(Ps - Pp)K'
+  let P = P + K * (Ps - Pp) * K.transpose();
+  Ok((x, P))
+}
+// 固定遅れ平滑化 ============================
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct FixedLagSmoother
+           <const M: usize,
+            const L: usize> // 遅れ+1(窓幅)
+{
+  // 窓内のフィルタ結果(古い順)
+  xs: [SMatrix<f32, M, 1>; L],
+  Ps: [SMatrix<f32, M, M>; L],
+  Fs: [SMatrix<f32, M, M>; L],
+  Qs: [SMatrix<f32, M, M>; L],
+  // 追加済み件数(L で頭打ち)
+  count: usize,
+  // 平滑化結果(窓の先頭=L-1ステップ前)
+  pub x: SMatrix<f32, M, 1>,
+  pub P: SMatrix<f32, M, M>,
+}
+#[allow(non_snake_case)]
+impl<const M: usize,
+     const L: usize>
+    FixedLagSmoother<M, L>
+{
+  pub fn new() -> Self {
+    // 次元数チェック
+    assert!(L >= 1,
+            // L >= 1 じゃないとダメ
+            "L >= 1, otherwise.");
+    Self {
+      xs: [SMatrix::<f32, M, 1>::zeros(); L],
+      Ps: [SMatrix::<f32, M, M>::zeros(); L],
+      Fs: [SMatrix::<f32, M, M>::zeros(); L],
+      Qs: [SMatrix::<f32, M, M>::zeros(); L],
+      count: 0,
+      x: SMatrix::<f32, M, 1>::zeros(),
+      P: SMatrix::<f32, M, M>::zeros(),
+    }
+  }
+  // フィルタ結果追加と平滑化
+  // 窓が埋まるまでは追加済みの範囲で平滑化した
+  // 最古の値(遅れはcount-1ステップ)
+  pub fn push(
+    &mut self,
+    x: &SMatrix<f32, M, 1>,
+    P: &SMatrix<f32, M, M>,
+    F: &SMatrix<f32, M, M>,
+    Q: &SMatrix<f32, M, M>,
+  ) -> Result<(), FilterError>
+  {
+    // 窓が埋まっていれば最古を捨てる
+    if self.count == L {
+      self.xs.rotate_left(1);
+      self.Ps.rotate_left(1);
+      self.Fs.rotate_left(1);
+      self.Qs.rotate_left(1);
+      self.count -= 1;
+    }
+    let k = self.count;
+    self.xs[k] = *x;
+    self.Ps[k] = *P;
+    self.Fs[k] = *F;
+    self.Qs[k] = *Q;
+    self.count += 1;
+    // 窓内を後ろ向きに平滑化し先頭を取得
+    let (mut xs, mut Ps) = (*x, *P);
+    for j in (0..k).rev() {
+      (xs, Ps) = rts_step(
+                   &self.xs[j], &self.Ps[j],
+                   &xs,         &Ps,
+                   &self.Fs[j+1],
+                   &self.Qs[j+1],
+                 )?;
+    }
+    self.x = xs;
+    self.P = Ps;
+    Ok(())
+  }
+  // FilterBaseからフィルタ結果を追加
+  // update後に呼出す
+  pub fn push_fb<const N: usize,
+                 const C: usize>(
+    &mut self,
+    fb: &FilterBase<M, N, C>,
+  ) -> Result<(), FilterError>
+  {
+    self.push(&fb.x, &fb.P, &fb.F, &fb.Q)
+  }
+  // 窓が埋まったか
+  // (x,PがL-1ステップ遅れの平滑化値か)
+  pub fn is_ready(&self) -> bool {
+    self.count == L
+  }
+  // 初期化
+  pub fn reset(&mut self) {
+    self.count = 0;
+  }
+}
+// --- Clippy対応 ---
+impl<const M: usize,
+     const L: usize>
+    Default for FixedLagSmoother<M, L> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
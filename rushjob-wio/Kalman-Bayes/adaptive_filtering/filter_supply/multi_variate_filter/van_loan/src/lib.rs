@@ -0,0 +1,146 @@
+#![no_std]
+// Van Loan法による連続時間モデルの離散化
+//
+// # 連続時間モデル
+//    dx/dt = A @ x + G @ w,  E[w w.T] = Qc δ(t)
+//      A : 状態行列           m x m
+//      G : ノイズ入力行列     m x l
+//      Qc: スペクトル密度     l x l
+//
+// # 離散化
+//    F = exp(A dt)
+//    Q = ∫(0 to dt) exp(Aτ) G Qc G.T exp(Aτ).T dτ
+//
+//  2m x 2mの行列
+//    X = [[-A, G @ Qc @ G.T],
+//         [ 0, A.T         ]] * dt
+//  の指数関数
+//    exp(X) = [[E11, E12],
+//              [  0, E22]]
+//  から
+//    F = E22.T
+//    Q = F @ E12
+//
+//  ※Xはブロック上三角なのでm x mのブロック
+//    3つ(E11, E12, E22)のまま計算する。
+//    (2mの次元指定が不要)
+//  行列指数関数はスケーリング&スクエアリング
+//  とテイラー展開で算出。
+//
+// 使い方(定常速度:x, vx)
+//   let A = SMatrix::<f32, 2, 2>::new(0., 1.,
+//                                     0., 0.);
+//   let G = SMatrix::<f32, 2, 1>::new(0., 1.);
+//   let Qc= SMatrix::<f32, 1, 1>::new(q);
+//   let (F, Q) = van_loan(&A, &G, &Qc, dt);
+// -----------------------------------------
+use nalgebra::SMatrix;
+
+// テイラー展開の次数
+const TAYLOR_ORDER: usize = 12;
+// スケーリング後のノルム上限
+const SCALE_NORM: f32 = 0.5;
+// スケーリング回数上限
+const SCALE_MAX: usize = 32;
+
+// 離散化(F, Q)
+#[allow(non_snake_case)]
+pub fn van_loan<const M: usize,
+                const L: usize>(
+  A : &SMatrix<f32, M, M>,
+  G : &SMatrix<f32, M, L>,
+  Qc: &SMatrix<f32, L, L>,
+  dt: f32,
+) -> (SMatrix<f32, M, M>, SMatrix<f32, M, M>)
+{
+  // X = [[-A, GQcG'], [0, A']] * dt
+  let X = (-A * dt,
+           G * Qc * G.transpose() * dt,
+           A.transpose() * dt);
+  let (_, E12, E22) = block_expm(&X);
+  let F = E22.transpose();
+  let Q = F * E12;
+  // 対称性を保つ
+  (F, (Q + Q.transpose()) * 0.5)
+}
+// 状態遷移行列のみ F = exp(A dt)
+#[allow(non_snake_case)]
+pub fn discretize_f<const M: usize>(
+  A : &SMatrix<f32, M, M>,
+  dt: f32,
+) -> SMatrix<f32, M, M>
+{
+  expm(&(A * dt))
+}
+// 行列指数関数 exp(X)
+#[allow(non_snake_case)]
+pub fn expm<const M: usize>(
+  X: &SMatrix<f32, M, M>,
+) -> SMatrix<f32, M, M>
+{
+  let z = SMatrix::<f32, M, M>::zeros();
+  block_expm(&(*X, z, z)).0
+}
+// ブロック上三角行列 [[a, b], [0, c]]
+type Block<const M: usize> = (
+  SMatrix<f32, M, M>,
+  SMatrix<f32, M, M>,
+  SMatrix<f32, M, M>,
+);
+// ブロック上三角行列の指数関数
+//   スケーリング:X / 2^s のノルム <= 0.5
+//   テイラー展開:I + X(I + X/2(I + X/3(...)))
+//   スクエアリング:s回2乗
+#[allow(non_snake_case)]
+fn block_expm<const M: usize>(X: &Block<M>)
+  -> Block<M>
+{
+  // スケーリング
+  let mut s = 0;
+  let mut h = *X;
+  while block_norm(&h) > SCALE_NORM &&
+        s < SCALE_MAX {
+    h = (h.0 * 0.5, h.1 * 0.5, h.2 * 0.5);
+    s += 1;
+  }
+  // テイラー展開(ホーナー法)
+  let I = SMatrix::<f32, M, M>::identity();
+  let mut E = (I, SMatrix::<f32, M, M>::zeros(), I);
+  for k in (1..=TAYLOR_ORDER).rev() {
+    let P = block_mul(&h, &E);
+    let r = 1.0 / k as f32;
+    E = (I + P.0 * r, P.1 * r, I + P.2 * r);
+  }
+  // スクエアリング
+  for _ in 0..s {
+    E = block_mul(&E, &E);
+  }
+  E
+}
+// ブロック上三角行列の積
+//   [[a, b], [0, c]] @ [[d, e], [0, f]]
+//   = [[a d, a e + b f], [0, c f]]
+fn block_mul<const M: usize>(x: &Block<M>,
+                             y: &Block<M>)
+  -> Block<M>
+{
+  (x.0 * y.0,
+   x.0 * y.1 + x.1 * y.2,
+   x.2 * y.2)
+}
+// ブロック上三角行列のノルム(行和の最大)
+fn block_norm<const M: usize>(x: &Block<M>)
+  -> f32
+{
+  (0..M).map(|r| {
+    let top: f32 = x.0.row(r).iter()
+                      .chain(x.1.row(r).iter())
+                      .map(|v| v.abs())
+                      .sum();
+    let bottom: f32 = x.2.row(r).iter()
+                         .map(|v| v.abs())
+                         .sum();
+    top.max(bottom)
+  })
+  .fold(0.0, f32::max)
+}
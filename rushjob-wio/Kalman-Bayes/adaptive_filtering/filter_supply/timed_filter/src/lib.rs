@@ -0,0 +1,239 @@
+#![no_std]
+// 時刻付きフィルタ処理
+//
+// 観測周期が一定でないセンサ向けに、
+// Filterトレイト実装フィルタを時刻で操作する。
+//
+// # 予測 predict_to(t)
+//    dt = t - 現在時刻
+//    F  = f_fn(dt)
+//    Q  = q_fn(dt, q_var)
+//    dt_set(dt) (UKF,EKFのfx用)
+//    predict()
+//
+// # 更新 update_at(t, z)
+//    t >= 現在時刻
+//      predict_to(t), update()
+//    t <  現在時刻 (遅れて届いた観測)
+//      履歴からtより前の更新結果を復元し、
+//      tの観測と、それ以降の観測を時刻順に
+//      再処理する(再予測まで含めて元の時刻に
+//      戻す)。
+//
+//  履歴は直近H件の観測(t, z)と更新後の
+//  フィルタを保持する。H件より古い観測は
+//  OutOfSequenceエラーとなる。
+//  q_fnにはDiscreteWhiteNoise::noise_block
+//  がそのまま渡せる(M == ブロック次元の場合)。
+//  ※u(制御入力)は保持しないので、
+//    再処理時は現在のuが使われる。
+// -----------------------------------------
+use filter_base::*;
+use nalgebra::SMatrix;
+
+// 観測履歴
+#[derive(Clone, Debug)]
+struct Record<KF, const N: usize> {
+  t   : f32,                // 観測時刻
+  z   : SMatrix<f32, N, 1>, // 観測値
+  post: KF,                 // 更新後フィルタ
+}
+
+#[derive(Clone, Debug)]
+pub struct TimedFilter<KF,
+                       const M: usize,
+                       const N: usize,
+                       const H: usize> // 履歴数
+{
+  // フィルタ
+  pub kf: KF,
+  // 現在時刻(フィルタ状態の時刻)
+  pub t : f32,
+  // 状態遷移行列作成関数 F(dt)
+  pub f_fn : fn(f32) -> SMatrix<f32, M, M>,
+  // プロセスノイズ作成関数 Q(dt, var)
+  pub q_fn : fn(f32, f32) -> SMatrix<f32, M, M>,
+  // プロセスノイズ分散
+  pub q_var: f32,
+  // 履歴の起点(最古の観測の直前の状態)
+  base : KF,
+  tb   : f32,
+  // 観測履歴(古い順)
+  recs : [Record<KF, N>; H],
+  count: usize,
+}
+impl<KF: Filter + Clone,
+     const M: usize,
+     const N: usize,
+     const H: usize>
+    TimedFilter<KF, M, N, H>
+{
+  pub fn new(
+    kf   : KF,
+    t    : f32,
+    f_fn : fn(f32) -> SMatrix<f32, M, M>,
+    q_fn : fn(f32, f32) -> SMatrix<f32, M, M>,
+    q_var: f32,
+  ) -> Self {
+    // 次元数チェック
+    assert!(H >= 1,
+            // H >= 1 じゃないとダメ
+            "H >= 1, otherwise.");
+    assert!(kf.P_as_slice().len() == M * M,
+            // P = M x M じゃないとダメ
+            // (f_fn,q_fnとフィルタの次元)
+            "P = M x M, otherwise.");
+    Self {
+      base : kf.clone(),
+      tb   : t,
+      recs : core::array::from_fn(|_|
+               Record {
+                 t,
+                 z   : SMatrix::<f32, N, 1>
+                              ::zeros(),
+                 post: kf.clone(),
+               }
+             ),
+      count: 0,
+      kf,
+      t,
+      f_fn,
+      q_fn,
+      q_var,
+    }
+  }
+  // 時刻tまで予測
+  pub fn predict_to(&mut self, t: f32)
+    -> Result<(), FilterError>
+  {
+    let dt = t - self.t;
+    if dt < 0.0 {
+      return Err(FilterError::OutOfSequence);
+    }
+    if dt == 0.0 {
+      return Ok(());
+    }
+    self.kf.F_from_slice(
+      (self.f_fn)(dt).as_slice()
+    );
+    self.kf.Q_from_slice(
+      (self.q_fn)(dt, self.q_var).as_slice()
+    );
+    self.kf.dt_set(dt);
+    self.kf.try_predict()?;
+    self.t = t;
+    Ok(())
+  }
+  // 時刻tの観測zで更新
+  pub fn update_at(
+    &mut self,
+    t: f32,
+    z: &SMatrix<f32, N, 1>,
+  ) -> Result<(), FilterError>
+  {
+    // 時刻順に届いた観測
+    if t >= self.t {
+      self.step(t, z)?;
+      self.push(t, z);
+      return Ok(());
+    }
+    // 遅れて届いた観測
+    // 挿入位置:t以前の観測件数
+    let mut j = self.recs[..self.count]
+                    .iter()
+                    .filter(|r| r.t <= t)
+                    .count();
+    if j == 0 && t < self.tb {
+      return Err(FilterError::OutOfSequence);
+    }
+    let t_now = self.t;
+    if self.count == H {
+      if j == 0 {
+        // 履歴より前:起点に直接反映
+        self.kf = self.base.clone();
+        self.t  = self.tb;
+        self.step(t, z)?;
+        self.base = self.kf.clone();
+        self.tb   = t;
+        self.replay(0)?;
+        return self.predict_to(t_now);
+      }
+      // 最古の観測を起点に繰入れ
+      self.drop_oldest();
+      j -= 1;
+    }
+    // j番目に挿入
+    self.recs[j..=self.count].rotate_right(1);
+    self.recs[j].t = t;
+    self.recs[j].z = *z;
+    self.count += 1;
+    // j番目以降を再処理し現在時刻に戻す
+    self.replay(j)?;
+    self.predict_to(t_now)
+  }
+  // 履歴クリア(現在の状態を起点にする)
+  pub fn reset(&mut self) {
+    self.base  = self.kf.clone();
+    self.tb    = self.t;
+    self.count = 0;
+  }
+  // 保持している観測件数
+  pub fn len(&self) -> usize {
+    self.count
+  }
+  pub fn is_empty(&self) -> bool {
+    self.count == 0
+  }
+  // 予測と更新
+  fn step(
+    &mut self,
+    t: f32,
+    z: &SMatrix<f32, N, 1>,
+  ) -> Result<(), FilterError>
+  {
+    self.predict_to(t)?;
+    (0..N).for_each(|i|
+      self.kf.z_set(i, z[i])
+    );
+    self.kf.try_update()
+  }
+  // 履歴追加
+  fn push(&mut self,
+          t: f32,
+          z: &SMatrix<f32, N, 1>)
+  {
+    if self.count == H {
+      self.drop_oldest();
+    }
+    let k = self.count;
+    self.recs[k].t    = t;
+    self.recs[k].z    = *z;
+    self.recs[k].post = self.kf.clone();
+    self.count += 1;
+  }
+  // 最古の観測を起点に繰入れ
+  fn drop_oldest(&mut self) {
+    self.base = self.recs[0].post.clone();
+    self.tb   = self.recs[0].t;
+    self.recs[..self.count].rotate_left(1);
+    self.count -= 1;
+  }
+  // j番目以降の観測を再処理
+  fn replay(&mut self, j: usize)
+    -> Result<(), FilterError>
+  {
+    (self.kf, self.t) = if j == 0 {
+      (self.base.clone(), self.tb)
+    } else {
+      (self.recs[j-1].post.clone(),
+       self.recs[j-1].t)
+    };
+    for i in j..self.count {
+      let (t, z) = (self.recs[i].t,
+                    self.recs[i].z);
+      self.step(t, &z)?;
+      self.recs[i].post = self.kf.clone();
+    }
+    Ok(())
+  }
+}
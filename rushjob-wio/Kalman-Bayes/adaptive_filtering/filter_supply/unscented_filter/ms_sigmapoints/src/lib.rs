@@ -3,6 +3,45 @@ use nalgebra::SMatrix;
 #[allow(unused_imports)]
 use micromath::F32Ext;
 
+// シグマポイント共通トレイト
+// UsKalmanFilterはこのトレイトを実装した
+// シグマポイントオブジェクトを受け付ける。
+//   MSSigmaPoints     : G = M x 2 + 1
+//   JulierSigmaPoints : G = M x 2 + 1
+//   SimplexSigmaPoints: G = M + 1
+#[allow(non_snake_case)]
+pub trait SigmaPoints<const M: usize,
+                      const G: usize>
+{
+  // 重み:平均
+  fn Wm(&self) -> &SMatrix<f32, 1, G>;
+  // 重み:共分散
+  fn Wc(&self) -> &SMatrix<f32, 1, G>;
+  // シグマポイント作成
+  // Pが正定値でない場合はNone
+  fn try_sigma_points(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    P: &SMatrix<f32, M, M>,
+  ) -> Option<SMatrix<f32, G, M>>;
+  fn sigma_points(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    P: &SMatrix<f32, M, M>,
+  ) -> SMatrix<f32, G, M>
+  {
+    self.try_sigma_points(x, P).unwrap()
+  }
+  // Pの下三角コレスキー因子L(P = L @ L.T)
+  // からシグマポイント作成
+  // 平方根UKFのようにPを分解せずに
+  // 因子を持ち回る場合に使用
+  fn sigma_points_sqrt(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    L: &SMatrix<f32, M, M>,
+  ) -> SMatrix<f32, G, M>;
+}
 // MerweScaledSigmaPoints
 #[derive(Clone, Debug)]
 #[allow(non_snake_case)]
@@ -82,45 +121,304 @@ impl<const M: usize, const G: usize>
     x: &SMatrix<f32, M, 1>,
     P: &SMatrix<f32, M, M>,
   ) ->  SMatrix<f32, G, M>
+  {
+    self.try_sigma_points(x, P).unwrap()
+  }
+  // シグマポイント作成
+  // Pが正定値でない場合はNone
+  #[allow(non_snake_case)]
+  pub fn try_sigma_points(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    P: &SMatrix<f32, M, M>,
+  ) ->  Option<SMatrix<f32, G, M>>
   { 
-    // ワーク行列
-    let xt = SMatrix::<f32, 1, M>
-                    ::from(x.transpose());
-    let mut urk = SMatrix::<f32, 1, M>
-                         ::zeros();
     // U=√(n+λ)Σ
     let n = self.n as f32;
 //    println!("P:{}",((n + self.lambda) * 
 //             bP).transpose());
     let U = ((n + self.lambda) * P)
-            .cholesky()  // コレスキー
-            .unwrap()
+            .cholesky()? // コレスキー
+            .l()         // 下三角
+            .transpose();// 上三角に変換
+    Some(symmetric_sigmas(x, &U, self.subtract))
+  }
+}
+#[allow(non_snake_case)]
+impl<const M: usize, const G: usize>
+     SigmaPoints<M, G> for MSSigmaPoints<M, G>
+{
+  fn Wm(&self) -> &SMatrix<f32, 1, G> {
+    &self.Wm
+  }
+  fn Wc(&self) -> &SMatrix<f32, 1, G> {
+    &self.Wc
+  }
+  fn try_sigma_points(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    P: &SMatrix<f32, M, M>,
+  ) -> Option<SMatrix<f32, G, M>>
+  {
+    MSSigmaPoints::try_sigma_points(self, x, P)
+  }
+  fn sigma_points_sqrt(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    L: &SMatrix<f32, M, M>,
+  ) -> SMatrix<f32, G, M>
+  {
+    // U=√(n+λ)L.T
+    let n = self.n as f32;
+    let U = (L * sqrt(n + self.lambda))
+            .transpose();
+    symmetric_sigmas(x, &U, self.subtract)
+  }
+}
+// JulierSigmaPoints =======================
+// κのみで広がりを決める。
+// κ = 3 - n がガウス分布に対して良いとされる
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct JulierSigmaPoints
+           <const M: usize, const G: usize>
+{
+  pub Wm: SMatrix<f32, 1, G>,    // 重み:状態
+  pub Wc: SMatrix<f32, 1, G>,    // 重み:観測
+  // 引き算関数
+  pub subtract:
+          fn(&SMatrix<f32, 1, M>,
+             &SMatrix<f32, 1, M>
+          ) -> SMatrix<f32, 1, M>,
+  // シグマ点パラメータ
+      kappa : f32,   // κ
+}
+impl<const M: usize, const G: usize>
+     JulierSigmaPoints<M, G>
+{
+  pub fn new(kappa: f32) -> Self {
+    // 次元数チェック
+    assert!(
+      (G - 1) / 2 == M,
+      // G = M x 2 + 1 じゃないとダメ
+      "G = M x 2 + 1, otherwise."
+    );
+
+    let mut sp = Self {
+      Wc: SMatrix::<f32, 1, G>::zeros(),
+      Wm: SMatrix::<f32, 1, G>::zeros(),
+      subtract: subtract_default,
+      kappa,
+    };
+    sp.compute_weights();
+    sp
+  }
+  // 重み計算
+  fn compute_weights(&mut self) {
+    let n = M as f32;
+    // Wi = 1/2(n+κ)
+    self.Wm.fill(0.5 / (n + self.kappa));
+    // W0 = κ/(n+κ)
+    self.Wm[(0, 0)] = self.kappa /
+                      (n + self.kappa);
+    // 共分散の重みは平均と同じ
+    self.Wc.copy_from(&self.Wm);
+  }
+}
+#[allow(non_snake_case)]
+impl<const M: usize, const G: usize>
+     SigmaPoints<M, G> for JulierSigmaPoints<M, G>
+{
+  fn Wm(&self) -> &SMatrix<f32, 1, G> {
+    &self.Wm
+  }
+  fn Wc(&self) -> &SMatrix<f32, 1, G> {
+    &self.Wc
+  }
+  fn try_sigma_points(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    P: &SMatrix<f32, M, M>,
+  ) -> Option<SMatrix<f32, G, M>>
+  {
+    // U=√(n+κ)Σ
+    let U = ((M as f32 + self.kappa) * P)
+            .cholesky()? // コレスキー
             .l()         // 下三角
             .transpose();// 上三角に変換
-    // シグマポイント行列
+    Some(symmetric_sigmas(x, &U, self.subtract))
+  }
+  fn sigma_points_sqrt(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    L: &SMatrix<f32, M, M>,
+  ) -> SMatrix<f32, G, M>
+  {
+    // U=√(n+κ)L.T
+    let U = (L * sqrt(M as f32 + self.kappa))
+            .transpose();
+    symmetric_sigmas(x, &U, self.subtract)
+  }
+}
+// SimplexSigmaPoints ======================
+// n+1点のシンプレックス配置。
+// シグマ点が約半分になるため計算量が少ない
+// 代わりに精度は落ちる。
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct SimplexSigmaPoints
+           <const M: usize, const G: usize>
+{
+  pub Wm: SMatrix<f32, 1, G>,    // 重み:状態
+  pub Wc: SMatrix<f32, 1, G>,    // 重み:観測
+  // 引き算関数
+  pub subtract:
+          fn(&SMatrix<f32, 1, M>,
+             &SMatrix<f32, 1, M>
+          ) -> SMatrix<f32, 1, M>,
+  // 単位シンプレックス(√n倍済)  m x g
+      I: SMatrix<f32, M, G>,
+}
+#[allow(non_snake_case)]
+impl<const M: usize, const G: usize>
+     SimplexSigmaPoints<M, G>
+{
+  pub fn new() -> Self {
+    // 次元数チェック
+    assert!(
+      G == M + 1,
+      // G = M + 1 じゃないとダメ
+      "G = M + 1, otherwise."
+    );
+
+    let mut sp = Self {
+      Wc: SMatrix::<f32, 1, G>::zeros(),
+      Wm: SMatrix::<f32, 1, G>::zeros(),
+      subtract: subtract_default,
+      I : SMatrix::<f32, M, G>::zeros(),
+    };
+    sp.compute_weights();
+    sp.compute_simplex();
+    sp
+  }
+  // 重み計算:全て 1/(n+1)
+  fn compute_weights(&mut self) {
+    self.Wm.fill(1. / G as f32);
+    self.Wc.fill(1. / G as f32);
+  }
+  // 単位シンプレックス作成
+  //   λ = n/(n+1)
+  //   I[0] = [-1/√2λ, 1/√2λ, 0, ..]
+  //   I[d-1] (d=2..n)
+  //     列0〜d-1 = 1/√(λd(d+1))
+  //     列d      = -d/√(λd(d+1))
+  //   I *= √n
+  fn compute_simplex(&mut self) {
+    let n = M as f32;
+    let lambda = n / (n + 1.);
+    let c = 1. / sqrt(2. * lambda);
+    self.I[(0, 0)] = -c;
+    self.I[(0, 1)] =  c;
+    (2..=M).for_each(|d| {
+      let df = d as f32;
+      let v = 1. / sqrt(lambda * df * (df + 1.));
+      (0..d).for_each(|j|
+        self.I[(d-1, j)] = v
+      );
+      self.I[(d-1, d)] = -df * v;
+    });
+    self.I *= sqrt(n);
+  }
+}
+#[allow(non_snake_case)]
+impl<const M: usize, const G: usize>
+     SigmaPoints<M, G> for SimplexSigmaPoints<M, G>
+{
+  fn Wm(&self) -> &SMatrix<f32, 1, G> {
+    &self.Wm
+  }
+  fn Wc(&self) -> &SMatrix<f32, 1, G> {
+    &self.Wc
+  }
+  fn try_sigma_points(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    P: &SMatrix<f32, M, M>,
+  ) -> Option<SMatrix<f32, G, M>>
+  {
+    Some(self.sigma_points_sqrt(
+           x, &P.cholesky()?.l()
+         ))
+  }
+  fn sigma_points_sqrt(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    L: &SMatrix<f32, M, M>,
+  ) -> SMatrix<f32, G, M>
+  {
+    let xt = SMatrix::<f32, 1, M>
+                    ::from(x.transpose());
+    // L @ I : 下三角因子でシンプレックスを
+    // 共分散の形に変換          m x g
+    let LI = L * self.I;
     let mut sigmas = SMatrix::<f32, G, M>
                             ::zeros();
-    // X0=μ
-    sigmas.row_mut(0).copy_from(&xt);
-    // シグマポイント設定
-    (0..self.n).for_each(|k| {
-        urk.copy_from(&U.row(k));
-        // X(1〜n)=μ+√(n+λ)
-        sigmas.row_mut(k+1).copy_from(
-          &((self.subtract)(
-               &xt, &(urk * -1.)
-           ))
-        );
-        // X(n+1〜2n)=μ-√(n+λ)
-        sigmas.row_mut(self.n+k+1).copy_from(
-          &((self.subtract)(
-               &xt, &urk
-           ))
-        );
+    (0..G).for_each(|k| {
+      // Xk = μ + (L @ I)[:, k]
+      sigmas.row_mut(k).copy_from(
+        &((self.subtract)(
+             &xt,
+             &(LI.column(k).transpose() * -1.)
+         ))
+      );
     });
     sigmas
   }
 }
+// --- 他関数 ---
+// 対称配置のシグマポイント作成
+// U: 行毎に広がり幅を持つ上三角行列
+//   X0 = μ, X(1〜n) = μ+U[k], X(n+1〜2n) = μ-U[k]
+#[allow(non_snake_case)]
+fn symmetric_sigmas
+   <const M: usize, const G: usize>(
+  x: &SMatrix<f32, M, 1>,
+  U: &SMatrix<f32, M, M>,
+  subtract: fn(&SMatrix<f32, 1, M>,
+               &SMatrix<f32, 1, M>
+            ) -> SMatrix<f32, 1, M>,
+) -> SMatrix<f32, G, M>
+{
+  // ワーク行列
+  let xt = SMatrix::<f32, 1, M>
+                  ::from(x.transpose());
+  let mut urk = SMatrix::<f32, 1, M>
+                       ::zeros();
+  // シグマポイント行列
+  let mut sigmas = SMatrix::<f32, G, M>
+                          ::zeros();
+  // X0=μ
+  sigmas.row_mut(0).copy_from(&xt);
+  // シグマポイント設定
+  (0..M).for_each(|k| {
+      urk.copy_from(&U.row(k));
+      // X(1〜n)=μ+U[k]
+      sigmas.row_mut(k+1).copy_from(
+        &(subtract(&xt, &(urk * -1.)))
+      );
+      // X(n+1〜2n)=μ-U[k]
+      sigmas.row_mut(M+k+1).copy_from(
+        &(subtract(&xt, &urk))
+      );
+  });
+  sigmas
+}
+// 平方根
+// ※micromathのsqrtは近似値のため
+//   精度の高いlibm版(ComplexField)を使用
+fn sqrt(v: f32) -> f32 {
+  <f32 as nalgebra::ComplexField>::sqrt(v)
+}
 // --- 関数定義型 デフォルト実装 ---
 // 引き算関数デフォルト
 fn subtract_default<const M: usize>(
@@ -131,4 +429,10 @@ where
 {
   a - b
 }
-
+// --- Clippy対応 ---
+impl<const M: usize, const G: usize>
+    Default for SimplexSigmaPoints<M, G> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,600 @@
+#![no_std]
+// 平方根無香料カルマンフィルタ(SR-UKF)
+//
+// Pの代わりに下三角コレスキー因子Sx
+// (P = Sx @ Sx.T)を持ち回るため、
+// f32で長時間回してもPが正定値から外れて
+// コレスキー分解に失敗することがない。
+//
+// # 予測
+//    X  = sigma_fn_sqrt(x, Sx)
+//    Y  = fx(X, dt, u)
+//    x  = mean(Y, wm)
+//    Sx = qr([√wc[i]*(Y[i]-x), √Q]).T
+//         wc[i] < 0 の点は cholupdate(-) で
+//         取り除く(ダウンデート)
+//    ※qrはギブンス回転で1行ずつ上三角化
+//
+// # 更新
+//    Z  = hx(Y)
+//    zp = mean(Z, wm)
+//    Sz = qr([√wc[i]*(Z[i]-zp), √R]).T
+//    y  = z - zp
+//    Pxz= i in 0..g
+//           (wc[i]*((Y[i]-x)*(Z[i]-zp).T))
+//           .sum()
+//    K  = Pxz @ inv(Sz.T) @ inv(Sz)
+//         (三角行列の前進/後退代入で算出)
+//    x  = x + K @ y
+//    U  = K @ Sz
+//    Sx = cholupdate(Sx, U[:, j], -1)
+//         j in 0..n
+//
+//  Sx: Pの下三角因子       m x m
+//  Sz: Sの下三角因子       n x n
+//  fx, hx, residual_x, residual_z,
+//  state_addはUsKalmanFilterと同じ。
+//  fb.P, fb.Sは毎回因子から再計算するので
+//  尤度計算などは従来通り使える。
+//  fb.Pを外部(IMM等)で書き換えた場合は
+//  次の予測時にSxを再分解する。
+// -----------------------------------------
+use nalgebra::{SMatrix, DimMin, Const};
+use ms_sigmapoints::*;
+use us_transform::*;
+use filter_base::*;
+
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct SrUsKalmanFilter
+           <const M:  usize,
+            const N:  usize,
+            const C:  usize,
+            const G:  usize,
+            const LR: usize,
+            const LC: usize,
+            SP = MSSigmaPoints<M, G>>
+{
+  // フィルタ共通変数定義
+  pub fb: FilterBase<M, N, C>,
+  // 無香料カルマンフィルタ用行列
+     zp: SMatrix<f32, 1, N>, // 無香料変換後z
+    Pxz: SMatrix<f32, M, N>, // 相互共分散行列
+  pub lm:SMatrix<f32, LR, LC>,// ﾗﾝﾄﾞﾏｰｸ
+  // 平方根行列
+  pub Sx: SMatrix<f32, M, M>, // Pの下三角因子
+  pub Sz: SMatrix<f32, N, N>, // Sの下三角因子
+     Ps: SMatrix<f32, M, M>, // Sx算出時のP
+  // シグマポイントデータ
+     Wm: SMatrix<f32, 1, G>,    // 重み:平均
+     Wc: SMatrix<f32, 1, G>,    // 重み:共分散
+  sigmas_f: SMatrix<f32, G, M>, // Σ点:状態
+  sigmas_h: SMatrix<f32, G, N>, // Σ点:観測
+  // 状態遷移関数(fx)
+  #[allow(clippy::type_complexity)]
+  pub fx : fn(&SMatrix<f32, M, 1>,   // x
+              &SMatrix<f32, C, 1>,   // u
+              &SMatrix<f32, M, M>,   // F
+              &SMatrix<f32, M, C>,   // B
+              f32,                   // dt
+           ) -> SMatrix<f32, M, 1>,
+  // 観測関数(hx)
+  #[allow(clippy::type_complexity)]
+  pub hx : fn(&SMatrix<f32, M, 1>,   // x
+              &SMatrix<f32, N, M>,   // H
+              &SMatrix<f32, LR, LC>, // lm
+           ) -> SMatrix<f32, N, 1>,
+  // 引き算関数(x:状態)
+  pub residual_x: fn(&SMatrix<f32, 1, M>,
+                     &SMatrix<f32, 1, M>
+                  ) -> SMatrix<f32, 1, M>,
+  // 引き算関数(z:観測値)
+  pub residual_z: fn(&SMatrix<f32, 1, N>,
+                     &SMatrix<f32, 1, N>
+                  ) -> SMatrix<f32, 1, N>,
+  // 足し算関数(x:状態)
+  pub state_add : fn(&SMatrix<f32, M, 1>,
+                     &SMatrix<f32, M, 1>
+                  ) -> SMatrix<f32, M, 1>,
+  // シグマポイントオブジェクト
+  pub sigmp: SP,
+  // 無香料変換(状態変数)オブジェクト
+  // ※平均計算関数(mean_fn)のみ使用
+  pub utx  : UsTransform<M, G>,
+  // 無香料変換(観測変数)オブジェクト
+  // ※平均計算関数(mean_fn)のみ使用
+  pub utz  : UsTransform<N, G>,
+  // 移動量
+  pub dt   : f32,
+}
+#[allow(non_snake_case)]
+impl<const M:  usize,
+     const N:  usize,
+     const C:  usize,
+     const G:  usize,
+     const LR: usize,
+     const LC: usize,
+     SP: SigmaPoints<M, G>>
+    SrUsKalmanFilter<M, N, C, G, LR, LC, SP>
+{
+  pub fn new(
+    sigmp: SP,
+    utx  : UsTransform<M, G>,
+    utz  : UsTransform<N, G>,
+  ) -> Self {
+    let mut ukf = Self {
+     fb: FilterBase::<M, N, C>::new(),
+     zp: SMatrix::<f32, 1,N> ::zeros(),
+    Pxz: SMatrix::<f32, M, N> ::zeros(),
+     lm: SMatrix::<f32, LR, LC> ::zeros(),
+     Sx: SMatrix::<f32, M, M> ::identity(),
+     Sz: SMatrix::<f32, N, N> ::zeros(),
+     Ps: SMatrix::<f32, M, M> ::identity(),
+     Wc: SMatrix::<f32, 1,G> ::zeros(),
+     Wm: SMatrix::<f32, 1,G> ::zeros(),
+      sigmas_f: SMatrix::<f32, G, M>::zeros(),
+      sigmas_h: SMatrix::<f32, G, N>::zeros(),
+      dt : 1.0,
+      fx : fx_default,
+      hx : hx_default,
+      residual_x: residual_x_default,
+      residual_z: residual_z_default,
+      state_add : state_add_default,
+      sigmp,
+      utx,
+      utz,
+    };
+    ukf.Wm.copy_from(ukf.sigmp.Wm());
+    ukf.Wc.copy_from(ukf.sigmp.Wc());
+
+    ukf
+  }
+  // 予測
+  pub fn predict(&mut self) {
+    self.try_predict().unwrap();
+  }
+  pub fn try_predict(&mut self)
+    -> Result<(), FilterError>
+  {
+    // fb.Pが外部で変更されていればSx再分解
+    self.sync_sqrt()?;
+    // 状態遷移後シグマ点Y(sigmas_f)作成
+    self.process_sigmas();
+    // 事前分布の平均 x = Σ(Wm[i]*Y[i])
+    let x = (self.utx.mean_fn)(
+              &self.sigmas_f,
+              &self.Wm,
+            );
+    // 事前分布の因子
+    // Sx = qr([√Wc*(Y-x), √Q]).T
+    let mut s = SMatrix::<f32, 1, M>::zeros();
+    let Sx = sqrt_from_rows(
+      &self.fb.Q,
+      (0..G).map(|i| {
+        s.copy_from(&self.sigmas_f.row(i));
+        ((self.residual_x)(&s, &x),
+         self.Wc[(0, i)])
+      }),
+    ).ok_or(FilterError::NotPositiveDefinite)?;
+    self.fb.x.copy_from(&x.transpose());
+    self.set_sqrt(Sx);
+    self.fb.check_finite()?;
+    // 事前分布x,Sxを元にシグマ点Yを再作成
+    self.sigmas_f = self.sigmp
+                    .sigma_points_sqrt(
+                      &self.fb.x,
+                      &self.Sx,
+                    );
+    Ok(())
+  }
+  // 更新
+  pub fn update(&mut self) {
+    self.try_update().unwrap();
+  }
+  pub fn try_update(&mut self)
+    -> Result<(), FilterError>
+  {
+    // 予測シグマ点Yを観測空間に変換し、
+    // シグマ点Z(sigmas_h)作成
+    self.observation_sigmas();
+    // 観測予測値平均 μz = Σ(Wm[i]*Z[i])
+    self.zp = (self.utz.mean_fn)(
+                &self.sigmas_h,
+                &self.Wm,
+              );
+    // 観測予測値共分散の因子
+    // Sz = qr([√Wc*(Z-μz), √R]).T
+    self.observation_sqrt(1.0)?;
+    // 残差計算:観測値z - 観測予測平均zp
+    // y = z - μz
+    self.fb.y = ((self.residual_z)(
+                   &self.fb.z.transpose(),
+                   &self.zp,
+                )).transpose();
+    // 観測ゲート:ゲート外は棄却かR拡大
+    let Some(g) = self.fb.try_gate()? else {
+      return Ok(());
+    };
+    if g != 1.0 {
+      self.observation_sqrt(g)?;
+    }
+    // 状態と観測値の相互共分散行列(Pxz)作成
+    self.cross_variance();
+    // カルマンゲイン算出
+    // K = Pxz * inv(Sz.T) * inv(Sz)
+    //   Sz * a = Pxz.T, Sz.T * K.T = a
+    let a  = self.Sz
+                 .solve_lower_triangular(
+                   &self.Pxz.transpose()
+                 )
+                 .ok_or(
+                   FilterError::SingularInnovation
+                 )?;
+    let Kt = self.Sz
+                 .tr_solve_lower_triangular(&a)
+                 .ok_or(
+                   FilterError::SingularInnovation
+                 )?;
+    self.fb.K = Kt.transpose();
+    // x = x + K * y
+    self.fb.x = (self.state_add)(
+                  &self.fb.x,
+                 &(self.fb.K * self.fb.y)
+                );
+    // P = P - (K*Sz)(K*Sz).T をダウンデートで
+    let U = self.fb.K * self.Sz;
+    let mut Sx = self.Sx;
+    for j in 0..N {
+      if !cholupdate(&mut Sx,
+                     U.column(j).into_owned(),
+                     -1.0) {
+        return Err(
+                 FilterError::NotPositiveDefinite
+               );
+      }
+    }
+    self.set_sqrt(Sx);
+    self.fb.check_finite()
+  }
+  // 状態遷移後シグマ点Y(sigmas_f)作成
+  pub fn process_sigmas(&mut self) {
+    // 事後分布の平均xと因子Sxから
+    // シグマ点Xを作成
+    let sigmas = self.sigmp.sigma_points_sqrt(
+                   &self.fb.x,
+                   &self.Sx,
+                 );
+    // ワーク行列
+    let mut s = SMatrix::<f32, 1, M>
+                       ::zeros();
+    // Y = fx(X, dt, u)
+    (0..G).for_each(|i| {
+      s.copy_from(&sigmas.row(i));
+
+      self.sigmas_f.row_mut(i).copy_from(
+        &((self.fx)(
+            &s.transpose(),
+            &self.fb.u,
+            &self.fb.F,
+            &self.fb.B,
+            self.dt,
+          ).transpose()
+         )
+      );
+    });
+  }
+  // 観測空間変換後シグマ点Z(sigmas_h)作成
+  pub fn observation_sigmas(&mut self) {
+    // ワーク行列
+    let mut s = SMatrix::<f32, M, 1>
+                       ::zeros();
+    // Z = h(Y)
+    (0..G).for_each(|i| {
+      s.copy_from(
+        &(self.sigmas_f.row(i).transpose())
+      );
+      self.sigmas_h.row_mut(i).copy_from(
+        &((self.hx)(
+            &s,
+            &self.fb.H,
+            &self.lm
+          ).transpose()
+         )
+      );
+    });
+  }
+  // 観測予測値共分散の因子Sz, S作成
+  // g: Rの倍率(観測ゲート用)
+  fn observation_sqrt(&mut self, g: f32)
+    -> Result<(), FilterError>
+  {
+    let mut h = SMatrix::<f32, 1, N>::zeros();
+    self.Sz = sqrt_from_rows(
+      &(self.fb.R * g),
+      (0..G).map(|i| {
+        h.copy_from(&self.sigmas_h.row(i));
+        ((self.residual_z)(&h, &self.zp),
+         self.Wc[(0, i)])
+      }),
+    ).ok_or(FilterError::SingularInnovation)?;
+    self.fb.S = self.Sz * self.Sz.transpose();
+    Ok(())
+  }
+  // 状態と観測値の相互共分散行列(Pxz)作成
+  pub fn cross_variance(&mut self) {
+    self.Pxz.fill(0.0);
+    // ワーク行列
+    let mut f = SMatrix::<f32, 1, M>
+                       ::zeros();
+    let mut h = SMatrix::<f32, 1, N>
+                       ::zeros();
+    // Pxz = (0..=2*n).sum(Wc[i]*(Y[i]-x)*
+    //                        (Z[i]-μz).T)
+    (0..G).for_each(|i| {
+      f.copy_from(&(self.sigmas_f.row(i)));
+      h.copy_from(&(self.sigmas_h.row(i)));
+      let dx = (self.residual_x)(
+                 &f,
+                 &self.fb.x.transpose(),
+               );
+      let dz = (self.residual_z)(
+                 &h,
+                 &self.zp,
+               );
+      self.Pxz += (dx.transpose() * dz) *
+                  *self.Wc.column(i)
+                       .as_scalar();
+    });
+  }
+  // fb.Pが外部で変更されていればSxを再分解
+  fn sync_sqrt(&mut self)
+    -> Result<(), FilterError>
+  {
+    if self.fb.P != self.Ps {
+      let Sx = self.fb.P.cholesky()
+                   .ok_or(
+                  FilterError::NotPositiveDefinite
+                   )?
+                   .l();
+      self.Sx = Sx;
+      self.Ps = self.fb.P;
+    }
+    Ok(())
+  }
+  // 因子Sxを設定しfb.Pを再計算
+  fn set_sqrt(&mut self,
+              Sx: SMatrix<f32, M, M>)
+  {
+    self.Sx = Sx;
+    self.fb.P = Sx * Sx.transpose();
+    self.Ps = self.fb.P;
+  }
+}
+// フィルタ共通トレイト実装
+impl<const M:  usize,
+     const N:  usize,
+     const C:  usize,
+     const G:  usize,
+     const LR: usize,
+     const LC: usize,
+     SP: SigmaPoints<M, G>>
+    Filter for
+    SrUsKalmanFilter<M, N, C, G, LR, LC, SP>
+where
+    Const<N>: DimMin<Const<N>,
+                     Output = Const<N>>,
+{
+  fn step_predict(&mut self)
+    -> Result<(), FilterError>
+  {
+    SrUsKalmanFilter::try_predict(self)
+  }
+  fn step_update(&mut self)
+    -> Result<(), FilterError>
+  {
+    SrUsKalmanFilter::try_update(self)
+  }
+  fn base(&self) -> &dyn FilterState {
+    &self.fb
+  }
+  fn base_mut(&mut self)
+    -> &mut dyn FilterState
+  {
+    &mut self.fb
+  }
+  fn dt_set(&mut self, dt: f32) {
+    self.dt = dt;
+  }
+}
+// --- 平方根行列演算 ---
+// ノイズ共分散と重み付き偏差から下三角因子
+// を作成
+//   L @ L.T = Σ(w[i]*d[i].T*d[i]) + noise
+//   w >= 0 の行はギブンス回転でQR、
+//   w <  0 の行はダウンデート
+// ダウンデートで正定値でなくなるか、
+// 負の重みの行が多すぎればNone
+#[allow(non_snake_case)]
+pub fn sqrt_from_rows<const K: usize>(
+  noise: &SMatrix<f32, K, K>,
+  rows : impl Iterator<Item =
+                 (SMatrix<f32, 1, K>, f32)>,
+) -> Option<SMatrix<f32, K, K>>
+{
+  // 上三角行列R(R.T @ R = Σ r.T @ r)
+  let mut R = SMatrix::<f32, K, K>::zeros();
+  // √noise の列を行として追加
+  let Ln = psd_cholesky(noise);
+  (0..K).for_each(|j|
+    givens_add_row(
+      &mut R,
+      Ln.column(j).transpose(),
+    )
+  );
+  // 負の重みは後でダウンデート
+  let mut neg = [(SMatrix::<f32, 1, K>
+                         ::zeros(), 0.0); 2];
+  let mut negc = 0;
+  for (d, w) in rows {
+    if w >= 0.0 {
+      givens_add_row(&mut R, d * sqrt(w));
+    } else {
+      // 負の重みは通常Wc[0]の1点のみ
+      // 保持数を超えたら分解できないのでNone
+      if negc == neg.len() {
+        return None;
+      }
+      neg[negc] = (d, w);
+      negc += 1;
+    }
+  }
+  let mut L = R.transpose();
+  for (d, w) in neg.iter().take(negc) {
+    if !cholupdate(&mut L,
+                   d.transpose() * sqrt(-w),
+                   -1.0) {
+      return None;
+    }
+  }
+  Some(L)
+}
+// 上三角Rに行rを追加(ギブンス回転)
+//   [R; r] を上三角化し R を更新する
+#[allow(non_snake_case)]
+pub fn givens_add_row<const K: usize>(
+  R: &mut SMatrix<f32, K, K>,
+  mut r: SMatrix<f32, 1, K>,
+)
+{
+  for k in 0..K {
+    if r[(0, k)] == 0.0 {continue}
+    let a = R[(k, k)];
+    let b = r[(0, k)];
+    let rho = sqrt(a * a + b * b);
+    let (c, s) = (a / rho, b / rho);
+    for j in k..K {
+      let rk = R[(k, j)];
+      let rj = r[(0, j)];
+      R[(k, j)] =  c * rk + s * rj;
+      r[(0, j)] = -s * rk + c * rj;
+    }
+  }
+}
+// ランク1更新(cholupdate)
+//   L @ L.T + sign * x @ x.T の下三角因子で
+//   Lを置き換える。sign = 1.0 or -1.0
+//   ダウンデートで正定値でなくなればfalse
+#[allow(non_snake_case)]
+pub fn cholupdate<const K: usize>(
+  L: &mut SMatrix<f32, K, K>,
+  mut x: SMatrix<f32, K, 1>,
+  sign: f32,
+) -> bool
+{
+  for k in 0..K {
+    if x[k] == 0.0 {continue}
+    let lkk = L[(k, k)];
+    let r2  = lkk * lkk + sign * x[k] * x[k];
+    if r2 <= 0.0 || lkk == 0.0 {
+      return false;
+    }
+    let r = sqrt(r2);
+    let c = r / lkk;
+    let s = x[k] / lkk;
+    L[(k, k)] = r;
+    for i in k+1..K {
+      L[(i, k)] = (L[(i, k)] +
+                   sign * s * x[i]) / c;
+      x[i] = c * x[i] - s * L[(i, k)];
+    }
+  }
+  true
+}
+// 半正定値行列用コレスキー分解
+//   Q, Rがゼロを含む(半正定値)場合でも
+//   L @ L.T = A となる下三角Lを返す。
+//   ピボットがゼロ以下の列はゼロとする。
+#[allow(non_snake_case)]
+pub fn psd_cholesky<const K: usize>(
+  A: &SMatrix<f32, K, K>,
+) -> SMatrix<f32, K, K>
+{
+  let mut L = SMatrix::<f32, K, K>::zeros();
+  for j in 0..K {
+    let d = A[(j, j)] -
+            (0..j).fold(0.0, |s, k|
+              s + L[(j, k)] * L[(j, k)]
+            );
+    if d <= f32::EPSILON * A[(j, j)].abs() {
+      continue;
+    }
+    let ljj = sqrt(d);
+    L[(j, j)] = ljj;
+    for i in j+1..K {
+      L[(i, j)] = (A[(i, j)] -
+                   (0..j).fold(0.0, |s, k|
+                     s + L[(i, k)] * L[(j, k)]
+                   )) / ljj;
+    }
+  }
+  L
+}
+// 平方根
+// ※micromathのsqrtは近似値のため
+//   精度の高いlibm版(ComplexField)を使用
+fn sqrt(v: f32) -> f32 {
+  <f32 as nalgebra::ComplexField>::sqrt(v)
+}
+// --- 関数定義型 デフォルト実装 ---
+// 状態遷移関数(fx)デフォルト
+#[allow(non_snake_case)]
+fn fx_default<const M: usize, const C: usize>(
+  x:  &SMatrix<f32, M, 1>,
+  u:  &SMatrix<f32, C, 1>,
+  F:  &SMatrix<f32, M, M>,
+  B:  &SMatrix<f32, M, C>,
+  _dt:f32,
+) -> SMatrix<f32, M, 1>
+{
+  F * x + B * u
+}
+// 観測関数(hx)デフォルト
+#[allow(non_snake_case)]
+fn hx_default
+   <const M:  usize,
+    const N:  usize,
+    const LR: usize,
+    const LC: usize>
+(
+  x:  &SMatrix<f32, M, 1>,
+  H:  &SMatrix<f32, N, M>,
+  _zt:&SMatrix<f32, LR, LC>,
+) -> SMatrix<f32, N, 1>
+{
+  H * x
+}
+// 引き算関数(x:状態)デフォルト
+fn residual_x_default<const M: usize>(
+  a: &SMatrix<f32, 1, M>,
+  b: &SMatrix<f32, 1, M>
+) -> SMatrix<f32, 1, M>
+{
+  a - b
+}
+// 引き算関数(z:観測値)デフォルト
+fn residual_z_default<const N: usize>(
+  a: &SMatrix<f32, 1, N>,
+  b: &SMatrix<f32, 1, N>
+) -> SMatrix<f32, 1, N>
+{
+  a - b
+}
+// 足し算関数(x:状態)デフォルト
+fn state_add_default<const M: usize>(
+  a: &SMatrix<f32, M, 1>,
+  b: &SMatrix<f32, M, 1>
+) -> SMatrix<f32, M, 1>
+{
+  a + b
+}
+// -------------------------------
@@ -74,13 +74,28 @@
 //     制御入力を状態 x の変化に変換する行列
 //シグマ点
 //  G: シグマ点数         g = m x 2 + 1
+//     SimplexSigmaPointsの場合 g = m + 1
+//  SP: シグマ点オブジェクト型
+//     SigmaPointsトレイト実装型
+//     (省略時MSSigmaPoints)
 //他
 //  S: 系不確実性あるいは発展共分散行列
 //                        n x n
 //  y: 残差               n x 1
 //  K: カルマンゲイン     m x n
+//
+// # 平滑化(rts_smoother) k = n-2 → 0
+//    X  = sigma_fn(x[k], P[k])
+//    Y  = fx(X, dt, u)
+//    xb, Pb = ut(Y, wm, wc, Q)
+//    Pxb = i in 0..g
+//           (wc[i]*((X[i]-x[k])*(Y[i]-xb).T))
+//           .sum()
+//    K  = Pxb * Pb.inv
+//    x[k] += K * (x[k+1] - xb)
+//    P[k] += K * (P[k+1] - Pb) * K.T
 // -----------------------------------------
-use nalgebra::SMatrix;
+use nalgebra::{SMatrix, DimMin, Const};
 use ms_sigmapoints::*;
 use us_transform::*;
 use filter_base::*;
@@ -93,7 +108,8 @@ pub struct UsKalmanFilter
             const C:  usize,
             const G:  usize,
             const LR: usize,
-            const LC: usize>
+            const LC: usize,
+            SP = MSSigmaPoints<M, G>>
 {
   // フィルタ共通変数定義
   pub fb: FilterBase<M, N, C>,  
@@ -133,7 +149,7 @@ pub struct UsKalmanFilter
                      &SMatrix<f32, M, 1>
                   ) -> SMatrix<f32, M, 1>,
   // シグマポイントオブジェクト
-  pub sigmp: MSSigmaPoints<M, G>,
+  pub sigmp: SP,
   // 無香料変換(状態変数)オブジェクト
   pub utx  : UsTransform<M, G>,
   // 無香料変換(観測変数)オブジェクト
@@ -147,11 +163,12 @@ impl<const M:  usize,
      const C:  usize,
      const G:  usize,
      const LR: usize,
-     const LC: usize>
-    UsKalmanFilter<M, N, C, G, LR, LC>
+     const LC: usize,
+     SP: SigmaPoints<M, G>>
+    UsKalmanFilter<M, N, C, G, LR, LC, SP>
 {
   pub fn new(
-    sigmp: MSSigmaPoints<M, G>,
+    sigmp: SP,
     utx  : UsTransform<M, G>,
     utz  : UsTransform<N, G>,
   ) -> Self {
@@ -174,15 +191,23 @@ impl<const M:  usize,
       utx,
       utz, 
     };
-    ukf.Wm.copy_from(&ukf.sigmp.Wm);
-    ukf.Wc.copy_from(&ukf.sigmp.Wc);
+    ukf.Wm.copy_from(ukf.sigmp.Wm());
+    ukf.Wc.copy_from(ukf.sigmp.Wc());
 
     ukf
   }
   // 予測
   pub fn predict(&mut self) {
+    self.try_predict().unwrap();
+  }
+  // エラー時はx, P, シグマ点を変えない
+  pub fn try_predict(&mut self) 
+    -> Result<(), FilterError> 
+  {
+    // エラー時に戻すシグマ点
+    let sigmas_prev = self.sigmas_f;
     // 状態遷移後シグマ点Y(sigmas_f)作成
-    self.process_sigmas();
+    self.try_process_sigmas()?;
     // 遷移後シグマ点Yに対する無香料変換で
     // 事前分布の平均xと共分散行列Pを算出
     // :x, P = UT(Y, Wm, Wc, Q) →
@@ -195,16 +220,39 @@ impl<const M:  usize,
                    &self.Wc, 
                    &self.fb.Q,
                  );
-    self.fb.x.copy_from(&x.transpose());
-    self.fb.P.copy_from(&P);
+    let x = x.transpose();
     // 事前分布x,Pを元にシグマ点Yを再作成
-    self.sigmas_f = self.sigmp.sigma_points(
-                      &self.fb.x, 
-                      &self.fb.P
-                    );
+    // (全て揃ってからfb.x, fb.P, sigmas_fに反映)
+    let sigmas = if x.iter().chain(P.iter())
+                     .all(|v| v.is_finite()) {
+                   self.sigmp
+                       .try_sigma_points(&x, &P)
+                       .ok_or(
+                     FilterError::NotPositiveDefinite
+                       )
+                 } else {
+                   Err(FilterError::NonFinite)
+                 };
+    match sigmas {
+      Ok(sigmas) => {
+        self.fb.x     = x;
+        self.fb.P     = P;
+        self.sigmas_f = sigmas;
+        Ok(())
+      }
+      Err(e) => {
+        self.sigmas_f = sigmas_prev;
+        Err(e)
+      }
+    }
   }
   // 更新
   pub fn update(&mut self) {
+    self.try_update().unwrap();
+  }
+  pub fn try_update(&mut self) 
+    -> Result<(), FilterError> 
+  {
     // 予測シグマ点Yを観測空間に変換し、
     // シグマ点Z(sigmas_h)作成
     self.observation_sigmas();
@@ -227,6 +275,14 @@ impl<const M:  usize,
                    &self.fb.z.transpose(),
                    &self.zp,
                 )).transpose();
+    // 観測ゲート:ゲート外は棄却かR拡大
+    // Pz = Pz + R * (g - 1)
+    let Some(g) = self.fb.try_gate()? else {
+      return Ok(());
+    };
+    if g != 1.0 {
+      self.fb.S += self.fb.R * (g - 1.0);
+    }
     // 状態と観測値の相互共分散行列(Pxz)作成
     // Pxz = (0..=2*n).sum(Wc[i]*(Y[i]-x)*
     //                        (Z[i]-μz).T
@@ -235,8 +291,12 @@ impl<const M:  usize,
     // Pxz(予測値と観測値の変動の類似性) /
     // Pz(観測値の不確実性)
     // K = Pxz * Pz.inv()
+    // Sが特異なら状態を変えずにエラー返却
     self.fb.K = self.Pxz *
-             self.fb.S.try_inverse().unwrap();
+             self.fb.S.try_inverse()
+                 .ok_or(
+                   FilterError::SingularInnovation
+                 )?;
     // 状態の新しい推定値を残差と
     // カルマンゲインを使って算出
     // x = x + K * y
@@ -249,18 +309,27 @@ impl<const M:  usize,
     self.fb.P -= self.fb.K * 
                  (self.fb.S *
                   self.fb.K.transpose());
+    self.fb.check_finite()
   }
   // 状態遷移後シグマ点Y(sigmas_f)作成
   pub fn process_sigmas(&mut self) {
+    self.try_process_sigmas().unwrap();
+  }
+  pub fn try_process_sigmas(&mut self) 
+    -> Result<(), FilterError> 
+  {
     // 重みWcとWmはコンストラクト時に生成済
     // :Wc, Wm = weight-function(n, param)
     // 事後分布の平均xと共分散行列Pから
     // シグマ点Xを作成
     // :X = sigma-function(x, P)
-    let sigmas = self.sigmp.sigma_points(
+    let sigmas = self.sigmp.try_sigma_points(
                    &self.fb.x, 
                    &self.fb.P
-                 );
+                 )
+                 .ok_or(
+                   FilterError::NotPositiveDefinite
+                 )?;
     // ワーク行列
     let mut s = SMatrix::<f32, 1, M>
                        ::zeros();
@@ -282,6 +351,7 @@ impl<const M:  usize,
          )
       );
     });
+    Ok(())
   }
   // 観測空間変換後シグマ点Z(sigmas_h)作成
   pub fn observation_sigmas(&mut self) {
@@ -305,6 +375,101 @@ impl<const M:  usize,
       );
     });
   }
+  // 無香料RTS平滑化
+  // xs, Psにフィルタ結果(更新後)を渡すと
+  // 平滑化結果で上書きする。
+  // Qs, dtsはk時点からk+1時点への予測で
+  // 使うQとdt。Noneならfb.Q, dtを使用。
+  // 制御入力等はフィルタの現在値を使う。
+  pub fn rts_smoother(
+    &self,
+    xs : &mut [SMatrix<f32, M, 1>],
+    Ps : &mut [SMatrix<f32, M, M>],
+    Qs : Option<&[SMatrix<f32, M, M>]>,
+    dts: Option<&[f32]>,
+  ) -> Result<(), FilterError>
+  {
+    // 長さチェック
+    assert!(
+      xs.len() == Ps.len() &&
+      Qs.is_none_or(|q| q.len() == xs.len()) &&
+      dts.is_none_or(|d| d.len() == xs.len()),
+      // 履歴の長さが揃ってないとダメ
+      "xs, Ps, Qs, dts length mismatch."
+    );
+    if xs.len() < 2 {
+      return Ok(());
+    }
+    // ワーク行列
+    let mut s  = SMatrix::<f32, 1, M>::zeros();
+    let mut sf = SMatrix::<f32, G, M>::zeros();
+    for k in (0..xs.len()-1).rev() {
+      let Q  = Qs.map_or(self.fb.Q, |q| q[k]);
+      let dt = dts.map_or(self.dt, |d| d[k]);
+      // k時点の平滑化前x,Pからシグマ点X作成
+      let sigmas = self.sigmp.try_sigma_points(
+                     &xs[k],
+                     &Ps[k],
+                   )
+                   .ok_or(
+                  FilterError::NotPositiveDefinite
+                   )?;
+      // 状態遷移後シグマ点Y = fx(X, dt, u)
+      (0..G).for_each(|i| {
+        s.copy_from(&sigmas.row(i));
+        sf.row_mut(i).copy_from(
+          &((self.fx)(
+              &s.transpose(),
+              &self.fb.u,
+              &self.fb.F,
+              &self.fb.B,
+              dt,
+            ).transpose()
+           )
+        );
+      });
+      // xb, Pb = UT(Y, Wm, Wc, Q)
+      let (xb, Pb) = self.utx.transform(
+                       &sf,
+                       &self.Wm,
+                       &self.Wc,
+                       &Q,
+                     );
+      // 相互共分散
+      // Pxb = (0..=2*n).sum(Wc[i]*(X[i]-x)*
+      //                         (Y[i]-xb).T
+      let xt  = xs[k].transpose();
+      let mut Pxb = SMatrix::<f32, M, M>
+                           ::zeros();
+      (0..G).for_each(|i| {
+        s.copy_from(&sigmas.row(i));
+        let dx = (self.residual_x)(&s, &xt);
+        s.copy_from(&sf.row(i));
+        let dy = (self.residual_x)(&s, &xb);
+        Pxb += (dx.transpose() * dy) *
+               *self.Wc.column(i).as_scalar();
+      });
+      // 平滑化ゲイン K = Pxb * Pb.inv()
+      let K = Pxb *
+              Pb.try_inverse()
+                .ok_or(
+                  FilterError::SingularCovariance
+                )?;
+      // x[k] = x[k] + K(x[k+1] - xb)
+      let dx = (self.residual_x)(
+                 &xs[k+1].transpose(),
+                 &xb,
+               );
+      xs[k] = (self.state_add)(
+                &xs[k],
+                &(K * dx.transpose()),
+              );
+      // P[k] = P[k] + K(P[k+1] - Pb)K'
+      Ps[k] += K * (Ps[k+1] - Pb) *
+               K.transpose();
+    }
+    Ok(())
+  }
   // 状態と観測値の相互共分散行列(Pxz)作成
   pub fn cross_variance(&mut self) {
     // 相互共分散行列初期化
@@ -336,6 +501,42 @@ impl<const M:  usize,
     });
   }
 }
+// フィルタ共通トレイト実装
+impl<const M:  usize,
+     const N:  usize,
+     const C:  usize,
+     const G:  usize,
+     const LR: usize,
+     const LC: usize,
+     SP: SigmaPoints<M, G>>
+    Filter for 
+    UsKalmanFilter<M, N, C, G, LR, LC, SP>
+where
+    Const<N>: DimMin<Const<N>,
+                     Output = Const<N>>,
+{
+  fn step_predict(&mut self) 
+    -> Result<(), FilterError> 
+  {
+    UsKalmanFilter::try_predict(self)
+  }
+  fn step_update(&mut self) 
+    -> Result<(), FilterError> 
+  {
+    UsKalmanFilter::try_update(self)
+  }
+  fn base(&self) -> &dyn FilterState {
+    &self.fb
+  }
+  fn base_mut(&mut self) 
+    -> &mut dyn FilterState 
+  {
+    &mut self.fb
+  }
+  fn dt_set(&mut self, dt: f32) {
+    self.dt = dt;
+  }
+}
 // --- 関数定義型 デフォルト実装 ---
 // 状態遷移関数(fx)デフォルト
 #[allow(non_snake_case)]
@@ -1,11 +1,174 @@
 #![no_std]
+// IMM(Interacting Multiple Model)推定
+//
+// Filterトレイト実装フィルタ(モデル)をFLC個
+// 保持し、混合・各モデルの予測/更新・
+// 推定値の合成をまとめて行う。
+//
+// # 予測 predict()
+//    混合:x0[j] = Σ(i)ω[i][j]*x[i]
+//         P0[j] = Σ(i)ω[i][j]*
+//                 ((x[i]-x0[j])(x[i]-x0[j]).T
+//                  + P[i])
+//    各モデルのx,Pをx0[j],P0[j]に置換えて予測
+//    推定値合成
+//
+// # 更新 update(z)
+//    各モデルで更新し対数尤度logL[i]を取得
+//    mu[i] = ∥ L[i]⋅cbar[i] ∥
+//      尤度は小さくなるとf32でアンダーフロー
+//      するので対数のまま
+//      logL[i] + log(cbar[i])
+//      をlog-sum-expで正規化する
+//    mu[i] = max(mu[i], mu_min)で再正規化
+//      (一度0になったモードが戻れるように)
+//    混合確率算出、推定値合成
+//
+// # 状態次元の異なるモデル
+//  混合と合成はM次元の共通状態で行い、
+//  モデル毎のStateMapで射影する。
+//   例:共通状態 = CA [px, vx, ax, py, vy, ay]
+//       CVモデル [px, vx, py, vy]
+//       StateMap::new(&[0, 1, 3, 4])
+//       をnew_with_mapsで渡す
+//  モデルにない要素(CVのax, ay)は
+//  fill_x, fill_Pの値(既定は0)で補う。
+// -----------------------------------------
+use filter_base::*;
 use nalgebra::SMatrix;
 
+// 状態射影(モデル状態 ⇔ 共通状態)
+#[derive(Clone, Copy, Debug)]
+#[allow(non_snake_case)]
+pub struct StateMap<const M: usize> {
+  // 共通状態の各要素に対応するモデル状態の添字
+  // (None:モデルにない要素)
+  pub idx   : [Option<usize>; M],
+  // モデルにない要素の値と分散
+  pub fill_x: SMatrix<f32, M, 1>,
+  pub fill_P: SMatrix<f32, M, 1>,
+}
+#[allow(non_snake_case)]
+impl<const M: usize> StateMap<M> {
+  // モデル状態k番目 → 共通状態idx[k]番目
+  pub fn new(idx: &[usize]) -> Self {
+    let mut map = Self {
+      idx   : [None; M],
+      fill_x: SMatrix::<f32, M, 1>::zeros(),
+      fill_P: SMatrix::<f32, M, 1>::zeros(),
+    };
+    for (k, i) in idx.iter().enumerate() {
+      assert!(*i < M,
+              // 添字 < M じゃないとダメ
+              "index < M, otherwise.");
+      map.idx[*i] = Some(k);
+    }
+    map
+  }
+  // 恒等射影(モデル状態 = 共通状態)
+  pub fn identity() -> Self {
+    let mut map = Self::new(&[]);
+    map.idx.iter_mut()
+           .enumerate()
+           .for_each(|(i, d)| *d = Some(i));
+    map
+  }
+  // モデルにない要素の値と分散を設定
+  pub fn fill_set(&mut self,
+                  r  : usize,
+                  x  : f32,
+                  var: f32)
+  {
+    self.fill_x[r] = x;
+    self.fill_P[r] = var;
+  }
+  // モデル状態の次元(対応する最大添字+1)
+  pub fn dim(&self) -> usize {
+    self.idx.iter()
+            .flatten()
+            .map(|i| i + 1)
+            .max()
+            .unwrap_or(0)
+  }
+  // モデル状態 → 共通状態
+  pub fn to_common(
+    &self,
+    f: &dyn Filter,
+    x: &mut SMatrix<f32, M, 1>,
+    P: &mut SMatrix<f32, M, M>,
+  ) {
+    let xs = f.x_as_slice();
+    let Ps = f.P_as_slice();
+    let n  = xs.len();
+    for r in 0..M {
+      match self.idx[r] {
+        Some(i) => {
+          x[r] = xs[i];
+          for c in 0..M {
+            P[(r, c)] = match self.idx[c] {
+              Some(j) => Ps[j * n + i],
+              None    => 0.,
+            };
+          }
+        }
+        None => {
+          x[r] = self.fill_x[r];
+          for c in 0..M {
+            P[(r, c)] = if r == c {
+              self.fill_P[r]
+            } else {
+              0.
+            };
+          }
+        }
+      }
+    }
+  }
+  // 共通状態 → モデル状態
+  // 共通状態にないモデル要素は変更しない
+  pub fn from_common(
+    &self,
+    x: &SMatrix<f32, M, 1>,
+    P: &SMatrix<f32, M, M>,
+    f: &mut dyn Filter,
+  ) {
+    let fs = f.base_mut();
+    let n  = fs.x_as_slice().len();
+    let xs = fs.x_as_mut_slice();
+    for (r, i) in self.idx.iter().enumerate() {
+      if let Some(i) = i {
+        xs[*i] = x[r];
+      }
+    }
+    let Ps = fs.P_as_mut_slice();
+    for (r, i) in self.idx.iter().enumerate() {
+      let Some(i) = i else { continue };
+      for (c, j) in self.idx.iter()
+                            .enumerate() {
+        if let Some(j) = j {
+          Ps[j * n + i] = P[(r, c)];
+        }
+      }
+    }
+  }
+}
+// --- Clippy対応 ---
+impl<const M: usize> Default for StateMap<M> {
+  fn default() -> Self {
+    Self::identity()
+  }
+}
+
 #[derive(Clone, Debug)]
 #[allow(non_snake_case)]
-pub struct IMMEstimator<const M  : usize,
+pub struct IMMEstimator<F,
+                        const M  : usize,
                         const FLC: usize>
 { // FLC=フィルタ数=モード数
+  // フィルタ(モデル)
+  pub filters: [F; FLC],
+  // 状態射影
+  pub maps : [StateMap<M>; FLC],
   // 混合状態変数
   pub x    : SMatrix<f32, M,   1>,
   // 混合共分散行列
@@ -13,28 +176,49 @@ pub struct IMMEstimator<const M  : usize,
   // モード確率
   pub mu   : SMatrix<f32, FLC, 1>,
   // マルコフ連鎖に対する遷移確率行列
+  // tpM[(i, j)]:モードiからjへの遷移確率
   pub tpM  : SMatrix<f32, FLC, FLC>,
   // 正規化係数:全確率
   pub cbar : SMatrix<f32, FLC, 1>,
   // 混合確率
   pub omega: SMatrix<f32, FLC, FLC>,
-  // 尤度(filters.likelihood)
-  pub f_lh : SMatrix<f32, FLC, 1>,
-  // 状態変数(filters.x)
+  // モード確率の下限
+  pub mu_min: f32,
+  // 対数尤度(filters.log_likelihood)
+  pub f_llh: SMatrix<f32, FLC, 1>,
+  // 状態変数(filters.x:共通状態)
   pub f_x  :[SMatrix<f32, M,   1>; FLC],
-  // 共分散行列(filters.P)
+  // 共分散行列(filters.P:共通状態)
   pub f_P  :[SMatrix<f32, M,   M>; FLC],
 }
 #[allow(non_snake_case)]
-impl <const M  : usize,
+impl <F: Filter,
+      const M  : usize,
       const FLC: usize>
-     IMMEstimator<M, FLC>
+     IMMEstimator<F, M, FLC>
 {
   pub fn new(
-    mu  : SMatrix<f32, FLC, 1>,
-    tpM : SMatrix<f32, FLC, FLC>,
+    filters: [F; FLC],
+    mu     : SMatrix<f32, FLC, 1>,
+    tpM    : SMatrix<f32, FLC, FLC>,
   ) -> Self {
-    Self {
+    Self::new_with_maps(
+      filters,
+      [StateMap::<M>::identity(); FLC],
+      mu,
+      tpM,
+    )
+  }
+  // 状態射影付きで作成(状態次元が異なる場合)
+  pub fn new_with_maps(
+    filters: [F; FLC],
+    maps   : [StateMap<M>; FLC],
+    mu     : SMatrix<f32, FLC, 1>,
+    tpM    : SMatrix<f32, FLC, FLC>,
+  ) -> Self {
+    let mut imm = Self {
+      filters,
+      maps,
       x    : SMatrix::<f32, M,   1>::zeros(),
       P    : SMatrix::<f32, M,   M>::zeros(),
       mu   : mu / mu.sum(), // ﾓｰﾄﾞ確率正規化
@@ -42,72 +226,89 @@ impl <const M  : usize,
       cbar : SMatrix::<f32, FLC, 1>::zeros(),
       omega: SMatrix::<f32, FLC, FLC>
                                    ::zeros(),
-      f_lh : SMatrix::<f32, FLC, 1>::zeros(),
+      mu_min: 1.0e-6,
+      f_llh: SMatrix::<f32, FLC, 1>::zeros(),
       f_x  :[SMatrix::<f32, M,   1>::zeros()
              ;FLC],
       f_P  :[SMatrix::<f32, M,   M>::zeros()
              ;FLC],
+    };
+    // 射影の添字がフィルタの状態次元内か
+    for (f, m) in imm.filters.iter()
+                     .zip(imm.maps.iter()) {
+      assert!(m.dim() <= f.x_as_slice().len(),
+              // 射影の添字 < 状態次元 じゃないとダメ
+              "map index < state dim, otherwise.");
     }
+    // 混合確率と推定値の初期化
+    imm.compute_mixing_probabilities();
+    imm.gather();
+    imm.compute_state_estimate();
+    imm
   }
   // 予測
   pub fn predict(&mut self) {
-    // この前に呼出元で各フィルタのxとPを
-    // f_xとf_Pにコピーしておく
-    let mut xms = self.f_x;
-    xms.iter_mut().for_each(|xm| xm.fill(0.));
-    
-    let mut Pms = self.f_P;
-    Pms.iter_mut().for_each(|Pm| Pm.fill(0.));
-
-    for ((ws, xm), Pm) in 
-                   self.omega.column_iter()
-                       .zip(xms.iter_mut())
-                       .zip(Pms.iter_mut()) {
-      // xm[j]=Σ(i=1 to N)w[i][j]*x[i]
-      for (x, w) in self.f_x.iter()
-                    .zip(ws.iter()) 
-      {
-        *xm += *w * *x;
-      }
-      // Pm[j]=Σ(i=1 to N)
-      //  w[i][j] * 
-      //  (x[i]-xm[i]}*(x[i]-xm[i]}.T + P[i])
-      for ((x, w), P) in self.f_x.iter()
-                         .zip(ws.iter())
-                         .zip(self.f_P.iter())
-      {
-        let y = *x - *xm;
-        *Pm += *w * (y * y.transpose() + *P);
-      }
+    self.try_predict().unwrap();
+  }
+  pub fn try_predict(&mut self)
+    -> Result<(), FilterError>
+  {
+    // 混合
+    self.gather();
+    self.mix();
+    // 混合した状態を各フィルタに戻して予測
+    for ((f, m), (x, P)) in
+                self.filters.iter_mut()
+                    .zip(self.maps.iter())
+                    .zip(self.f_x.iter()
+                         .zip(self.f_P.iter()))
+    {
+      m.from_common(x, P, f);
+      f.try_predict()?;
     }
-    self.f_x = xms;
-    self.f_P = Pms;
-    // この後に呼出元で各フィルタのxとPを
-    // f_xとf_Pの値に置き換えて後
-    // 各フィルタでpredictを実行
-    // 各フィルタのxとPをf_zとf_Pにコピーし
-    // compute_state_estimateを呼出す
-  }
-  // 更新
-  pub fn update(&mut self) {
-    // この前に呼出元で各フィルタのxとPと
-    // likelihoodを
-    // f_xとf_Pとf_lhにコピーしておく
-
+    // 混合推定値算出
+    self.gather();
+    self.compute_state_estimate();
+    Ok(())
+  }
+  // 更新(z:観測値)
+  pub fn update(&mut self, z: &[f32]) {
+    self.try_update(z).unwrap();
+  }
+  pub fn try_update(&mut self, z: &[f32])
+    -> Result<(), FilterError>
+  {
+    for (f, llh) in self.filters.iter_mut()
+                        .zip(self.f_llh.iter_mut())
+    {
+      // 観測値設定
+      z.iter().enumerate()
+       .for_each(|(r, z)| f.z_set(r, *z));
+      f.try_update()?;
+      // 対数尤度を設定
+      *llh = f.log_likelihood();
+    }
+    self.gather();
     // モード確率を更新:確率*尤度
-    // mu[i]=∥ L[i]⋅cbar[i] ∥ 
-    self.mu = self.f_lh
-                  .component_mul(&self.cbar);
-    self.mu /= self.mu.sum(); // 正規化
+    // mu[i]=∥ L[i]⋅cbar[i] ∥
+    let lw = self.f_llh.zip_map(&self.cbar,
+               |l, c|
+                 l + <f32 as nalgebra::ComplexField>
+                       ::ln(c)
+             );
+    log_normalize(lw.as_slice(),
+                  self.mu.as_mut_slice(),
+                  self.mu_min);
     // 混合確率算出
     self.compute_mixing_probabilities();
     // IMMアルゴリズム推定値算出
     self.compute_state_estimate();
+    Ok(())
   }
   // 混合確率算出
   pub fn compute_mixing_probabilities(
     &mut self
-  ) 
+  )
   {
   // ω[i][j]=∥ μ[i]⋅M[i][j] ∥
   // μ[i](モード確率)を事前分布、
@@ -117,12 +318,12 @@ impl <const M  : usize,
   // で算出
 
     // cbar(正規化係数:全確率)算出
-    // muxM
-    self.cbar = self.tpM * self.mu;
-    // 
+    // cbar[j]=Σ(i)mu[i]*M[i][j]
+    self.cbar = self.tpM.transpose() * self.mu;
+    //
     for i in 0..FLC {for j in 0..FLC {
-      self.omega[(i, j)] = 
-          (self.tpM[(i, j)]*self.mu[(i, 0)]) / 
+      self.omega[(i, j)] =
+          (self.tpM[(i, j)]*self.mu[(i, 0)]) /
           self.cbar[(j, 0)];
     }}
   }
@@ -138,12 +339,54 @@ impl <const M  : usize,
     //   ((x[j]-x)*(x[j]-x).T + P[j])
     self.P.fill(0.);
     for ((x, P), mu) in self.f_x.iter()
-                        .zip(self.f_P.iter()) 
+                        .zip(self.f_P.iter())
                         .zip(self.mu.iter()) {
       let y = *x - self.x;
-      self.P += *mu * 
+      self.P += *mu *
                 (y * y.transpose() + *P);
     }
   }
+  // 各フィルタのxとPを共通状態でf_x,f_Pへ
+  fn gather(&mut self) {
+    for ((f, m), (x, P)) in
+                self.filters.iter()
+                    .zip(self.maps.iter())
+                    .zip(self.f_x.iter_mut()
+                         .zip(self.f_P.iter_mut()))
+    {
+      m.to_common(f, x, P);
+    }
+  }
+  // 混合:f_x,f_Pを混合した値に置換え
+  fn mix(&mut self) {
+    let mut xms = self.f_x;
+    xms.iter_mut().for_each(|xm| xm.fill(0.));
+
+    let mut Pms = self.f_P;
+    Pms.iter_mut().for_each(|Pm| Pm.fill(0.));
+
+    for ((ws, xm), Pm) in
+                   self.omega.column_iter()
+                       .zip(xms.iter_mut())
+                       .zip(Pms.iter_mut()) {
+      // xm[j]=Σ(i=1 to N)w[i][j]*x[i]
+      for (x, w) in self.f_x.iter()
+                    .zip(ws.iter())
+      {
+        *xm += *w * *x;
+      }
+      // Pm[j]=Σ(i=1 to N)
+      //  w[i][j] *
+      //  (x[i]-xm[i]}*(x[i]-xm[i]}.T + P[i])
+      for ((x, w), P) in self.f_x.iter()
+                         .zip(ws.iter())
+                         .zip(self.f_P.iter())
+      {
+        let y = *x - *xm;
+        *Pm += *w * (y * y.transpose() + *P);
+      }
+    }
+    self.f_x = xms;
+    self.f_P = Pms;
+  }
 }
-  
\ No newline at end of file
@@ -0,0 +1,259 @@
+#![no_std]
+// MMAE(Multiple Model Adaptive Estimation)
+//
+// Filterトレイト実装フィルタ(モデル)を最大FLC個
+// 保持し、各モデルを独立に予測/更新して
+// 累積尤度による重みで推定値を合成する。
+// (IMMと違いモデル間の混合はしない)
+//
+// # 予測 predict()
+//    各モデルで予測、推定値合成
+//
+// # 更新 update(z)
+//    各モデルで更新し対数尤度logL[i]を取得
+//    w[i] = ∥ L[i]⋅w[i] ∥
+//      対数のまま log(w[i]) + logL[i] を
+//      log-sum-expで正規化する
+//    w[i] = max(w[i], w_min)で再正規化
+//    推定値合成
+//      x = Σ w[i]*x[i]
+//      P = Σ w[i]*((x[i]-x)(x[i]-x).T + P[i])
+//
+// # モデルの追加/削除
+//  add(f, map, w)  : 重みwで追加
+//                    (既存モデルの重みは1-w倍)
+//  remove(i)       : 削除し残りを再正規化
+//  状態次元の異なるモデルはimm_estimatorの
+//  StateMapでM次元の共通状態に射影する。
+// -----------------------------------------
+use filter_base::*;
+pub use imm_estimator::StateMap;
+use nalgebra::SMatrix;
+
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct MMAEEstimator<F,
+                         const M  : usize,
+                         const FLC: usize>
+{ // FLC=最大フィルタ数
+  // フィルタ(モデル)
+  pub filters: [Option<F>; FLC],
+  // 状態射影
+  pub maps : [StateMap<M>; FLC],
+  // 合成状態変数
+  pub x    : SMatrix<f32, M,   1>,
+  // 合成共分散行列
+  pub P    : SMatrix<f32, M,   M>,
+  // モデル重み(空きは0)
+  pub w    : SMatrix<f32, FLC, 1>,
+  // モデル重みの下限
+  pub w_min: f32,
+  // 対数尤度(filters.log_likelihood)
+  pub f_llh: SMatrix<f32, FLC, 1>,
+  // 状態変数(filters.x:共通状態)
+  pub f_x  :[SMatrix<f32, M,   1>; FLC],
+  // 共分散行列(filters.P:共通状態)
+  pub f_P  :[SMatrix<f32, M,   M>; FLC],
+}
+#[allow(non_snake_case)]
+impl <F: Filter,
+      const M  : usize,
+      const FLC: usize>
+     MMAEEstimator<F, M, FLC>
+{
+  pub fn new() -> Self {
+    Self {
+      filters: core::array::from_fn(|_| None),
+      maps : [StateMap::<M>::identity(); FLC],
+      x    : SMatrix::<f32, M,   1>::zeros(),
+      P    : SMatrix::<f32, M,   M>::zeros(),
+      w    : SMatrix::<f32, FLC, 1>::zeros(),
+      w_min: 1.0e-6,
+      f_llh: SMatrix::<f32, FLC, 1>::zeros(),
+      f_x  :[SMatrix::<f32, M,   1>::zeros()
+             ;FLC],
+      f_P  :[SMatrix::<f32, M,   M>::zeros()
+             ;FLC],
+    }
+  }
+  // モデル追加(w:追加するモデルの重み)
+  // 既存モデルの重みは(1-w)倍する
+  // 最初のモデルの重みは1
+  // 戻り値:格納位置(空きがなければNone)
+  pub fn add(&mut self,
+             f  : F,
+             map: StateMap<M>,
+             w  : f32,
+  ) -> Option<usize>
+  {
+    assert!(0.0 < w && w <= 1.0,
+            // 0 < w <= 1 じゃないとダメ
+            "0 < w <= 1, otherwise.");
+    assert!(map.dim() <= f.x_as_slice().len(),
+            // 射影の添字 < 状態次元 じゃないとダメ
+            "map index < state dim, otherwise.");
+    let i = self.filters.iter()
+                        .position(|f| f.is_none())?;
+    if self.is_empty() {
+      self.w[i] = 1.0;
+    } else {
+      self.w   *= 1.0 - w;
+      self.w[i] = w;
+    }
+    self.filters[i] = Some(f);
+    self.maps[i]    = map;
+    self.normalize();
+    self.gather();
+    self.compute_state_estimate();
+    Some(i)
+  }
+  // モデル削除
+  // 残りのモデルの重みは再正規化する
+  pub fn remove(&mut self, i: usize)
+    -> Option<F>
+  {
+    let f = self.filters[i].take()?;
+    self.w[i] = 0.0;
+    if !self.is_empty() {
+      self.normalize();
+      self.gather();
+      self.compute_state_estimate();
+    }
+    Some(f)
+  }
+  // モデル数
+  pub fn len(&self) -> usize {
+    self.filters.iter()
+                .filter(|f| f.is_some())
+                .count()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.filters.iter().all(|f| f.is_none())
+  }
+  // 予測
+  pub fn predict(&mut self) {
+    self.try_predict().unwrap();
+  }
+  pub fn try_predict(&mut self)
+    -> Result<(), FilterError>
+  {
+    for f in self.filters.iter_mut()
+                         .flatten() {
+      f.try_predict()?;
+    }
+    // 推定値合成
+    self.gather();
+    self.compute_state_estimate();
+    Ok(())
+  }
+  // 更新(z:観測値)
+  pub fn update(&mut self, z: &[f32]) {
+    self.try_update(z).unwrap();
+  }
+  pub fn try_update(&mut self, z: &[f32])
+    -> Result<(), FilterError>
+  {
+    for (f, llh) in self.filters.iter_mut()
+                        .zip(self.f_llh.iter_mut())
+    {
+      let Some(f) = f else { continue };
+      // 観測値設定
+      z.iter().enumerate()
+       .for_each(|(r, z)| f.z_set(r, *z));
+      f.try_update()?;
+      // 対数尤度を設定
+      *llh = f.log_likelihood();
+    }
+    // 重みを更新:重み*尤度
+    // w[i]=∥ L[i]⋅w[i] ∥
+    let mut lw = [0.0f32; FLC];
+    for ((lw, w), llh) in lw.iter_mut()
+                            .zip(self.w.iter())
+                            .zip(self.f_llh.iter())
+    {
+      *lw = <f32 as nalgebra::ComplexField>
+              ::ln(*w) + llh;
+    }
+    self.normalize_log(&lw);
+    // 推定値合成
+    self.gather();
+    self.compute_state_estimate();
+    Ok(())
+  }
+  // 推定値合成(空きは重み0)
+  pub fn compute_state_estimate(&mut self) {
+    // x=Σ(j=1 to N)w[j]*x[j]
+    self.x.fill(0.);
+    for (x, w) in self.f_x.iter()
+                  .zip(self.w.iter()) {
+      self.x += *x * *w;
+    }
+    // P=Σ(j=1 to N)w[j]*
+    //   ((x[j]-x)*(x[j]-x).T + P[j])
+    self.P.fill(0.);
+    for ((x, P), w) in self.f_x.iter()
+                       .zip(self.f_P.iter())
+                       .zip(self.w.iter()) {
+      let y = *x - self.x;
+      self.P += *w *
+                (y * y.transpose() + *P);
+    }
+  }
+  // 重みの正規化(w_minで下限)
+  fn normalize(&mut self) {
+    let mut lw = [0.0f32; FLC];
+    lw.iter_mut()
+      .zip(self.w.iter())
+      .for_each(|(lw, w)|
+        *lw = <f32 as nalgebra::ComplexField>
+                ::ln(*w)
+      );
+    self.normalize_log(&lw);
+  }
+  // 対数重みから正規化した重みを算出
+  // 空きの重みは0
+  fn normalize_log(&mut self, lw: &[f32; FLC]) {
+    let mut v = [0.0f32; FLC];
+    let mut p = [0.0f32; FLC];
+    let mut k = 0;
+    for (f, lw) in self.filters.iter()
+                       .zip(lw.iter()) {
+      if f.is_some() {
+        v[k] = *lw;
+        k += 1;
+      }
+    }
+    log_normalize(&v[..k], &mut p[..k],
+                  self.w_min);
+    let mut ps = p.iter();
+    for (f, w) in self.filters.iter()
+                      .zip(self.w.iter_mut()) {
+      *w = match f {
+        Some(_) => *ps.next().unwrap(),
+        None    => 0.0,
+      };
+    }
+  }
+  // 各フィルタのxとPを共通状態でf_x,f_Pへ
+  fn gather(&mut self) {
+    for ((f, m), (x, P)) in
+                self.filters.iter()
+                    .zip(self.maps.iter())
+                    .zip(self.f_x.iter_mut()
+                         .zip(self.f_P.iter_mut()))
+    {
+      if let Some(f) = f {
+        m.to_common(f, x, P);
+      }
+    }
+  }
+}
+// --- Clippy対応 ---
+impl<F: Filter,
+     const M  : usize,
+     const FLC: usize>
+    Default for MMAEEstimator<F, M, FLC> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
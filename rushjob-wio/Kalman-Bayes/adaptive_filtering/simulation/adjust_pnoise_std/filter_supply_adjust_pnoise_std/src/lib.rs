@@ -1,5 +1,9 @@
 #![no_std]
 use filter_base::*;
+// ﾌﾟﾛｾｽﾉｲｽﾞ調整関数
+use adaptive_noise::noise_adjust_std;
+// Filterトレイト(利用側でのメソッド呼出用)
+pub use filter_base::Filter;
 use mv_kalmanfilter::*;
 use discrete_white_noise::*;
 use nalgebra::SMatrix;
@@ -13,13 +17,11 @@ type Kf1 = KalmanFilter<2, 1, 1>;
 pub enum Filters {
   Kf1(Kf1), 
 }
-#[allow(non_snake_case)]
-impl Filters {
-  // filter_supply_implマクロによる実装
-  filter_supply_impl!(
-    Kf1,
-  );
-}
+// filter_supply_implマクロによるFilter実装
+filter_supply_impl!(
+  Filters:
+  Kf1,
+);
 // 供給フィルタの格納 ====================
 pub fn filter_supply() -> [Filters; FLC] { 
   [Filters::Kf1(mv_filter1( 1000., 2.0)),
@@ -68,41 +70,10 @@ fn mv_filter1(
   // ﾉｲｽﾞ調整ｶｳﾝﾀにゼロを設定
   kf.fb.ns_count = Some(0);
   // ﾌﾟﾛｾｽﾉｲｽﾞ調整関数入替え
-  kf.fb.noise_adjust_fn = noise_adjust_fn;
+  kf.fb.noise_adjust_fn = noise_adjust_std;
   // ﾌﾟﾛｾｽﾉｲｽﾞ調整設定
   kf.fb.phi = Some(phi);
   kf.fb.Q_scale_factor = Q_sacle_factor; 
   kf.fb.Q_adjust_param = std_scale;
   kf
 }
-// 関数定義型:ﾌﾟﾛｾｽﾉｲｽﾞ調整関数実装
-#[allow(non_snake_case)]
-fn noise_adjust_fn
-   <const M: usize, const N: usize>
-(
-  Q: &mut SMatrix<f32, M, M>, // Q
-  y:     &SMatrix<f32, N, 1>, // y
-  S:     &SMatrix<f32, N, N>, // S
-  phi     : &mut f32,         // phi
-  ns_count: &mut usize,       // ns_count
-  Q_scale_factor: f32,
-  std_scale: f32,
-)
-{  
-  const B: usize = 2; // ﾌﾟﾛｾｽﾉｲｽﾞﾌﾞﾛｯｸ
-  let std = S[(0, 0)].sqrt();
-  let dt = 0.1;
-  if y[(0, 0)].abs() > std_scale*std {
-    *phi += Q_scale_factor;
-    *ns_count += 1;
-  } else if *ns_count > 0 {
-    *phi -= Q_scale_factor;
-    *ns_count -= 1;
-  }
-  // ノイズ設定
-    // ノイズブロック作成
-  let bn: SMatrix<f32, B, B> =  
-    DiscreteWhiteNoise::noise_block(dt, *phi);
-    // プロセスノイズ設定
-  Q.copy_from_slice(bn.as_slice());
-}
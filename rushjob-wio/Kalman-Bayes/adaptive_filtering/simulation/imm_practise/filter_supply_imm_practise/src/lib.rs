@@ -1,5 +1,7 @@
 #![no_std]
 use filter_base::*;
+// Filterトレイト(利用側でのメソッド呼出用)
+pub use filter_base::Filter;
 use mv_kalmanfilter::*;
 use nalgebra::SMatrix;
 
@@ -14,13 +16,11 @@ type Kf1 = KalmanFilter<M, N, C>;
 pub enum Filters {
   Kf1(Kf1), 
 }
-#[allow(non_snake_case)]
-impl Filters {
-  // filter_supply_implマクロによる実装
-  filter_supply_impl!(
-    Kf1,
-  );
-}
+// filter_supply_implマクロによるFilter実装
+filter_supply_impl!(
+  Filters:
+  Kf1,
+);
 // 供給フィルタの格納 ====================
 pub fn filter_supply() -> [Filters; FLC] { 
   [ca_filter_normal(),
@@ -1,5 +1,7 @@
 #![no_std]
 use filter_base::*;
+// Filterトレイト(利用側でのメソッド呼出用)
+pub use filter_base::Filter;
 use mv_kalmanfilter::*;
 use discrete_white_noise::*;
 use nalgebra::SMatrix;
@@ -15,14 +17,12 @@ pub enum Filters {
   Kf1(Kf1), 
   Kf2(Kf2), 
 }
-#[allow(non_snake_case)]
-impl Filters {
-  // filter_supply_implマクロによる実装
-  filter_supply_impl!(
-    Kf1,
-    Kf2,
-  );
-}
+// filter_supply_implマクロによるFilter実装
+filter_supply_impl!(
+  Filters:
+  Kf1,
+  Kf2,
+);
 // 供給フィルタの格納 ====================
 pub fn filter_supply() -> [Filters; FLC] { 
   [cv_filter(),
@@ -59,7 +59,7 @@ fn main() -> ! {
       )
       .unwrap();
   // フィルタ/グラフ供給 -----------------
-  let mut filters = filter_supply();
+  let mut mmae = mmae_supply();
   let (mut graph_box, mut g) = graph_supply();
   // 画面クリア ---------------------------
   Rectangle::new(
@@ -75,34 +75,16 @@ fn main() -> ! {
     g.mode_scale().draw(&mut display).unwrap()
   );
   // 繰り返し -----------------------------
-  let mut xs = [0.0f32; FLC];
-  let mut lhs = [0.0f32; FLC];
   let dt = 0.1;
   let gd = GenerateData::new(120, 0.8);
   for (i, (_, z)) in gd.into_iter()
                        .enumerate() {
-    for (j, f) in filters.iter_mut()
-                         .enumerate() {
-      // フィルタ操作
-      f.z_set(0, z.0);     // 観測値設定
-      f.predict();         // 予測
-      f.update();          // 更新
-      xs[j] = f.x(0);      // 予測値
-      lhs[j] = f.cum_lh(); // 累積尤度値
-    }
-    // ΣLj[k]pj[k-1]
-    let lhs_sum: f32 = lhs.iter().sum();
-
-    for (j, f) in filters.iter_mut()
-                         .enumerate() {
-      // Li[k]pi[k-1]/ΣLj[k]pj[k-1]
-      lhs[j] /= lhs_sum;
-      f.cum_lh_set(lhs[j]);
-    }
-    let x_blend = xs.iter().zip(lhs.iter())
-                    .fold(0.0, |s, (x, l)|
-                       s + x * l
-                    );
+    // MMAE操作
+    // 各フィルタの予測、更新と
+    // 累積尤度による重み付け
+    mmae.predict();           // 予測
+    mmae.update(&[z.0]);      // 更新
+    let x_blend = mmae.x[0];  // 予測値
     // グラフ描画
     let x = i as f32 * dt;
     g[2].set_data(x, z.0); 
@@ -1,8 +1,12 @@
 #![no_std]
 use filter_base::*;
+// Filterトレイト(利用側でのメソッド呼出用)
+pub use filter_base::Filter;
 use mv_kalmanfilter::*;
 use discrete_white_noise::*;
 use nalgebra::SMatrix;
+// MMAE推定(利用側でのメソッド呼出用)
+pub use mmae_estimator::*;
 #[allow(unused_imports)]
 use micromath::F32Ext;
 
@@ -15,20 +19,34 @@ pub enum Filters {
   Kf1(Kf1), 
   Kf2(Kf2), 
 }
-#[allow(non_snake_case)]
-impl Filters {
-  // filter_supply_implマクロによる実装
-  filter_supply_impl!(
-    Kf1,
-    Kf2,
-  );
-}
+// filter_supply_implマクロによるFilter実装
+filter_supply_impl!(
+  Filters:
+  Kf1,
+  Kf2,
+);
 // 供給フィルタの格納 ====================
 pub fn filter_supply() -> [Filters; FLC] { 
   [cv_filter(),
    ca_filter(),
   ]
 }
+// MMAE推定の供給 ========================
+// 共通状態はCAモデル[x, vx, ax]
+// CVモデル[x, vx]はaxを0で補う
+pub fn mmae_supply() -> MMAEEstimator<Filters, 3, FLC> {
+  let mut mmae = MMAEEstimator::<Filters, 3, FLC>::new();
+  let maps = [StateMap::<3>::new(&[0, 1]),
+              StateMap::<3>::identity(),
+             ];
+  for (f, m) in filter_supply().into_iter()
+                               .zip(maps) {
+    // 累積尤度の初期値を重みとする
+    let w = f.cum_lh();
+    mmae.add(f, m, w);
+  }
+  mmae
+}
 // 定常速度フィルタ =======================
 fn cv_filter() -> Filters {
 // シミュレーション設定
@@ -58,7 +76,7 @@ fn cv_filter() -> Filters {
     // プロセスノイズ設定
   kf.fb.Q.copy_from(&bn);
   // 累積尤度計算設定
-  kf.fb.cum_lh = Some(0.8);
+  kf.fb.cum_lh_set(0.8);
 
   Filters::Kf1(kf)
 }
@@ -92,7 +110,7 @@ fn ca_filter() -> Filters {
     // プロセスノイズ設定
   kf.fb.Q.copy_from(&bn);
   // 累積尤度計算設定
-  kf.fb.cum_lh = Some(0.2);
+  kf.fb.cum_lh_set(0.2);
 
   Filters::Kf2(kf)
 }
@@ -35,14 +35,11 @@ fn main() {
                     pf.pt[0], pf.wg[0]);
       println!("neff:{}", pf.neff());
     }
-    // 再サンプリング判定
-    if pf.neff() < sim.jval {
-      // 再サンプリング
-      pf.resample();
-      if i % sim.dstep == 0 {
-        println!("pt_res[0]:{:?}, w:{}", 
-                    pf.pt[0], pf.wg[0]);
-      }
+    // 再サンプリング(方針による)
+    if pf.resample_if_needed() &&
+       i % sim.dstep == 0 {
+      println!("pt_res[0]:{:?}, w:{}", 
+                  pf.pt[0], pf.wg[0]);
     }
     // 平均、分散算出
     let (mu, var) = pf.estimate();
@@ -1,11 +1,143 @@
 #![no_std]
 
 use core::f32::consts::PI;
+use core::cmp::Ordering;
 use rand_distr::{Distribution, Uniform, 
                  Normal};
 use rand::prelude::*;
+use filter_snapshot::{SnapWriter, SnapReader,
+                      snapshot_len};
+pub use filter_snapshot::SnapshotError;
+#[allow(unused_imports)]
 use micromath::F32Ext;
 
+// 関数定義型 ===============================
+// 粒子生成関数
+pub type CreateFn<const PM: usize> =
+  fn(&mut [[f32; PM]],      // 粒子(pt)
+     &[[f32; 2]],           // ﾊﾟﾗﾒｰﾀ
+     &mut StdRng,           // 乱数
+    );
+// 再ｻﾝﾌﾟﾘﾝｸﾞ関数
+pub type ResampleFn<const PM: usize> =
+  fn(&mut [f32],            // 重み(wg)
+     &[[f32; PM]],          // 粒子(pt)
+     &mut [[f32; PM]],      // 粒子(pts)
+     &mut StdRng,           // 乱数
+    );
+// 状態遷移関数
+pub type FxFn<const PM: usize> =
+  fn(&mut [[f32; PM]],      // 粒子(pt)
+     &[f32],                // 制御入力(u)
+     &[f32],                // ﾌﾟﾛｾｽﾉｲｽ(Q)
+     f32,                   // dt
+     &mut StdRng,           // 乱数
+    );
+// 観測関数
+pub type HxFn<const PM: usize,
+              const PD: usize,
+              const PN: usize> =
+  fn([f32; PM],             // 粒子(pt)
+     [f32; PD],             // ﾗﾝﾄﾞﾏｰｸ
+     usize,                 // 位置次元数
+    ) -> [f32; PN];         // 観測形式値
+
+// 再サンプリング方針
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResamplePolicy {
+  // 毎回再サンプリング
+  Always,
+  // 実効サンプルサイズ neff < r * active
+  //   (active: 有効粒子数)
+  Neff(f32),
+  // 正規化エントロピー
+  //   -Σw*ln(w) / ln(active) < r
+  Entropy(f32),
+}
+// 粒子フィルタの種類
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PfVariant {
+  // SIR(ブートストラップ)
+  Sir,
+  // 正則化粒子フィルタ
+  //   再サンプリング後に粒子をカーネルで
+  //   ずらす(劣化防止)。
+  //   x += h * D * ε, ε ~ N(0, I)
+  //     D: 再ｻﾝﾌﾟﾘﾝｸﾞ前の重み付き共分散の
+  //        コレスキー因子
+  //     h: 最適バンド幅 * 倍率(f32)
+  //        A * n^(-1/(PM+4))
+  //        A = (4/(PM+2))^(1/(PM+4))
+  Regularized(f32),
+  // 補助粒子フィルタ
+  //   ①先読み点μ = fx(粒子, Q=0)の尤度で
+  //     1段目の重み w*p(z|μ) を作り再ｻﾝﾌﾟﾘﾝｸﾞ
+  //   ②予測、2段目の重み p(z|x) / p(z|μ)
+  //   fxはQ=0でﾉｲｽﾞを加えないこと。
+  Auxiliary,
+}
+// KLDサンプリング設定
+//   再サンプリング時に粒子を1個ずつ引き、
+//   粒子が入るビン(格子)の数kから
+//   KL距離をε以下にする粒子数
+//     n = (k-1)/2ε *
+//         (1 - 2/9(k-1) + √(2/9(k-1)) * z)³
+//   に達したら打ち切る(最大PG)。
+//   z: 標準正規分布の1-δ分位点
+//   粒子は重みに従って多項再ｻﾝﾌﾟﾘﾝｸﾞで引く
+//   (resample_fnは使わない)。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KldSampling<const PM: usize> {
+  pub bin    : [f32; PM], // ビン幅(0は無視)
+  pub epsilon: f32,       // KL距離の上限ε
+  pub z      : f32,       // 1-δ分位点
+  pub n_min  : usize,     // 最小粒子数
+}
+impl<const PM: usize> KldSampling<PM> {
+  pub fn new(bin: [f32; PM]) -> Self {
+    Self {
+      bin,
+      epsilon: 0.05,
+      z      : 2.326, // δ = 0.01
+      n_min  : 50,
+    }
+  }
+  // ビン数kに必要な粒子数
+  pub fn bound(&self, k: usize) -> usize {
+    if k < 2 {
+      return 0;
+    }
+    let k1 = (k - 1) as f32;
+    let a  = 2.0 / (9.0 * k1);
+    (k1 / (2.0 * self.epsilon) *
+     (1.0 - a + a.sqrt() * self.z).powi(3)
+    ).ceil() as usize
+  }
+  // 粒子のビン(ハッシュ値、0以外)
+  fn bin_hash(&self, pt: &[f32; PM]) -> u32 {
+    let h = pt.iter()
+              .zip(self.bin.iter())
+              .filter(|(_, b)| **b > 0.0)
+              .fold(0x811C_9DC5u32, |h, (p, b)| {
+                let c = (p / b).floor() as i32;
+                c.to_le_bytes().iter()
+                 .fold(h, |h, v|
+                   (h ^ *v as u32)
+                   .wrapping_mul(0x0100_0193)
+                 )
+              });
+    h.max(1)
+  }
+}
+// 推定値(全次元)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate<const PM: usize> {
+  pub mean: [f32; PM],       // 平均
+  pub cov : [[f32; PM]; PM], // 共分散
+  pub map : Option<[f32; PM]>,// MAP粒子
+}
+// スナップショットのmagic
+const SNAPSHOT_MAGIC: &[u8; 4] = b"PFSN";
 // 粒子フィルタ
 #[derive(Clone, Debug)]
 #[allow(non_snake_case)]
@@ -31,29 +163,23 @@ pub struct ParticleFilter
   pub Q   : [f32; PC],       // ﾌﾟﾛｾｽﾉｲｽﾞ
   pub dt  : f32,             // Dt
   pub rng : StdRng,          // 乱数
-  pub create_fn:             // 粒子生成関数
-    fn(&mut [[f32; PM]],      // 粒子(pt)
-       &[[f32; 2]],           // ﾊﾟﾗﾒｰﾀ
-       &mut StdRng,           // 乱数
-      ),
-  pub resample_fn:           // 再ｻﾝﾌﾟﾘﾝｸﾞ関数
-    fn(&mut [f32],            // 重み(wg)
-       &[[f32; PM]],          // 粒子(pt)
-       &mut [[f32; PM]],      // 粒子(pts)
-       &mut StdRng,           // 乱数
-      ),
-  pub fx:                    // 状態遷移関数
-    fn(&mut [[f32; PM]],      // 粒子(pt)
-       &[f32],                // 制御入力(u)
-       &[f32],                // ﾌﾟﾛｾｽﾉｲｽ(Q)
-       f32,                   // dt
-       &mut StdRng,           // 乱数
-      ),
-  pub hx:                    // 観測関数
-    fn([f32; PM],             // 粒子(pt)
-       [f32; PD],             // ﾗﾝﾄﾞﾏｰｸ
-       usize,                 // 位置次元数
-      ) -> [f32; PN],         // 観測形式値
+  pub create_fn  : CreateFn<PM>,   // 粒子生成関数
+  pub resample_fn: ResampleFn<PM>, // 再ｻﾝﾌﾟﾘﾝｸﾞ関数
+  pub fx : FxFn<PM>,               // 状態遷移関数
+  pub hx : HxFn<PM, PD, PN>,       // 観測関数
+  pub resample_policy: ResamplePolicy, // 再ｻﾝﾌﾟﾘﾝｸﾞ方針
+  pub last_neff     : f32,   // 直近の実効ｻﾝﾌﾟﾙｻｲｽﾞ
+  pub last_resampled: bool,  // 直近で再ｻﾝﾌﾟﾘﾝｸﾞ
+  pub resample_count: usize, // 再ｻﾝﾌﾟﾘﾝｸﾞ回数
+  // 直近の更新(再ｻﾝﾌﾟﾘﾝｸﾞ前)で重み最大の粒子
+  pub last_map      : Option<[f32; PM]>,
+  // KLDｻﾝﾌﾟﾘﾝｸﾞ
+  //   設定時はresample_fnを使わず、
+  //   常に多項再ｻﾝﾌﾟﾘﾝｸﾞ(kld_resample)
+  pub kld   : Option<KldSampling<PM>>,
+  bins      : [u32; PG],     // KLDのビン(作業用)
+  pub active: usize,         // 有効粒子数(pt[..active])
+  pub variant: PfVariant,    // 粒子フィルタの種類
 }
 impl
   <const PM : usize,         // 粒子次元
@@ -92,6 +218,16 @@ impl
       // 観測関数ﾃﾞﾌｫﾙﾄ
       hx:          hx_default
                    ::<PM, PD, PN>,
+      // 再ｻﾝﾌﾟﾘﾝｸﾞ方針ﾃﾞﾌｫﾙﾄ:neff < active/2
+      resample_policy: ResamplePolicy::Neff(0.5),
+      last_neff     : PG as f32,
+      last_resampled: false,
+      resample_count: 0,
+      last_map      : None,
+      kld   : None,
+      bins  : [0; PG],
+      active: PG,
+      variant: PfVariant::Sir,
     }
   }
   // 粒子生成
@@ -106,9 +242,9 @@ impl
       &mut self.rng,
     );
     // 重みの初期化
-    self.wg.iter_mut().for_each(|w|
-      *w = 1.0 / PG as f32
-    );
+    self.active = PG;
+    self.weight_reset();
+    self.last_map = None;
     //
     self
   }
@@ -116,7 +252,7 @@ impl
   pub fn predict(&mut self) -> &mut Self {
     // 制御入力、ﾉｲｽﾞをもとに粒子を移動
     (self.fx)(
-       &mut self.pt,
+       &mut self.pt[..self.active],
        &self.u,
        &self.Q,
        self.dt,
@@ -125,6 +261,11 @@ impl
     self
   }
   // 更新
+  // 重みは対数で計算する
+  //   log(w) += Σlog_pdf(h, R, z)
+  // 最大値を引いてexpし正規化する
+  // (ﾗﾝﾄﾞﾏｰｸが多い、Rが小さい場合でも
+  //  重みが全て0にならない)
   pub fn update(
     &mut self,
     // ﾗﾝﾄﾞﾏｰｸ毎の[[位置]], [観測値]
@@ -132,75 +273,311 @@ impl
     z : &[[f32; PN]],
   ) -> &mut Self 
   {
-    let mut wgsum = 0.0;
-    for (r, pt) in self.pt.iter()
-                          .enumerate() {
-      for i in 0..z.len() {
-        // 粒子を観測値形式に変換
-        let h = (self.hx)(*pt, lm[i], PD);
-        for c in 0..h.len() {
-          // 粒子と観測値の比を算出
-          let pdf  = pdf(
-                       h[c], 
-                       self.R[c],
-                       z[i][c]
-                     );
-          // 比を重みに掛ける
-          self.wg[r] *= pdf;
-      }}
-      self.wg[r] += 1.0E-32f32;
-      wgsum += self.wg[r];
+    let n = self.active;
+    for (pt, wg) in self.pt[..n].iter()
+                       .zip(self.wg[..n].iter_mut()) {
+      // 重みを対数に変換(0は-∞)
+      // ※micromathのln(0)は-∞にならない
+      *wg = if *wg > 0.0 {
+              wg.ln()
+            } else {
+              f32::NEG_INFINITY
+            };
+      // 対数尤度を対数重みに足す
+      *wg += log_lh(self.hx, &self.R, pt, lm, z);
     }
-    // 重みの正規化
-    self.wg.iter_mut().for_each(|w|
-      *w /= wgsum
-    );
+    // 重みの正規化(log-sum-exp)
+    log_normalize(&mut self.wg[..n]);
+    // 再ｻﾝﾌﾟﾘﾝｸﾞで重みが一様になる前に記録
+    self.last_map = Some(self.map_particle());
     //
     self
   }
+  // 重み最大の粒子
+  fn map_particle(&self) -> [f32; PM] {
+    let (r, _) = self.wg[..self.active].iter()
+                   .enumerate()
+                   .fold((0, f32::NEG_INFINITY),
+                     |(r, m), (i, w)|
+                       if *w > m {(i, *w)} else {(r, m)}
+                   );
+    self.pt[r]
+  }
   // 実効サンプルサイズ
   pub fn neff(&self) -> f32 {
     1.0 /
-    self.wg.iter()
+    self.wg[..self.active].iter()
         .fold(0.0, |s, wg| s + wg.powi(2))
   }
-  // 再サンプリング
-  pub fn resample(&mut self) -> &mut Self {
-    // 再ｻﾝﾌﾟﾘﾝｸﾞ関数
-    (self.resample_fn)(
-       &mut self.wg,
-       &self.pt, 
-       &mut self.pts,
+  // 重みの正規化エントロピー(0〜1)
+  //   -Σw*ln(w) / ln(active)
+  // ※lnはmicromathの近似値のため1で頭打ち
+  pub fn entropy(&self) -> f32 {
+    let n = self.active;
+    if n < 2 {
+      return 1.0;
+    }
+    (-self.wg[..n].iter()
+          .filter(|w| **w > 0.0)
+          .fold(0.0, |s, w| s + w * w.ln())
+     / (n as f32).ln()
+    ).min(1.0)
+  }
+  // 再サンプリング判定
+  // (last_neffを更新する)
+  pub fn resample_needed(&mut self) -> bool {
+    self.last_neff = self.neff();
+    match self.resample_policy {
+      ResamplePolicy::Always     => true,
+      ResamplePolicy::Neff(r)    =>
+        self.last_neff < r * self.active as f32,
+      ResamplePolicy::Entropy(r) =>
+        self.entropy() < r,
+    }
+  }
+  // 方針に従って再サンプリング
+  // 戻り値:再サンプリングしたか
+  pub fn resample_if_needed(&mut self) -> bool {
+    self.last_resampled = self.resample_needed();
+    if self.last_resampled {
+      self.resample();
+    }
+    self.last_resampled
+  }
+  // 予測、更新、方針に従って再サンプリング
+  // 補助粒子フィルタは1段目で毎回再ｻﾝﾌﾟﾘﾝｸﾞ
+  pub fn step(
+    &mut self,
+    // ﾗﾝﾄﾞﾏｰｸ毎の[[位置]], [観測値]
+    lm: &[[f32; PD]],
+    z : &[[f32; PN]],
+  ) -> &mut Self
+  {
+    if self.variant == PfVariant::Auxiliary {
+      self.last_neff      = self.neff();
+      self.last_resampled = true;
+      return self.auxiliary_step(lm, z);
+    }
+    self.predict()
+        .update(lm, z)
+        .resample_if_needed();
+    self
+  }
+  // 補助粒子フィルタ
+  fn auxiliary_step(
+    &mut self,
+    lm: &[[f32; PD]],
+    z : &[[f32; PN]],
+  ) -> &mut Self
+  {
+    let n = self.active;
+    // ①1段目の重み log(w) + log p(z|μ)
+    self.lookahead();
+    for (mu, wg) in self.pts[..n].iter()
+                        .zip(self.wg[..n].iter_mut()) {
+      *wg = if *wg > 0.0 {
+              wg.ln() + log_lh(self.hx, &self.R, mu, lm, z)
+            } else {
+              f32::NEG_INFINITY
+            };
+    }
+    log_normalize(&mut self.wg[..n]);
+    // 1段目の重みで再ｻﾝﾌﾟﾘﾝｸﾞ(親粒子を選択)
+    self.resample();
+    // 親粒子の先読み点の対数尤度を引く
+    let n = self.active;
+    self.lookahead();
+    for (mu, wg) in self.pts[..n].iter()
+                        .zip(self.wg[..n].iter_mut()) {
+      let l = log_lh(self.hx, &self.R, mu, lm, z);
+      *wg = if l.is_finite() {-l} else {0.0};
+    }
+    // ②予測、2段目の重み log p(z|x) - log p(z|μ)
+    self.predict();
+    for (pt, wg) in self.pt[..n].iter()
+                       .zip(self.wg[..n].iter_mut()) {
+      *wg += log_lh(self.hx, &self.R, pt, lm, z);
+    }
+    log_normalize(&mut self.wg[..n]);
+    self.last_map = Some(self.map_particle());
+    self
+  }
+  // 先読み点 μ = fx(粒子, Q=0) をptsへ
+  fn lookahead(&mut self) {
+    let n = self.active;
+    self.pts[..n].copy_from_slice(&self.pt[..n]);
+    (self.fx)(
+       &mut self.pts[..n],
+       &self.u,
+       &[0.0; PC],
+       self.dt,
        &mut self.rng,
     );
+  }
+  // 再サンプリング
+  // KLDｻﾝﾌﾟﾘﾝｸﾞ設定時は有効粒子数も更新
+  // (resample_fnは使わない)
+  pub fn resample(&mut self) -> &mut Self {
+    let n = self.active;
+    // 正則化用:再ｻﾝﾌﾟﾘﾝｸﾞ前の共分散
+    let cov = match self.variant {
+      PfVariant::Regularized(_) =>
+        Some(self.weighted_cov().1),
+      _ => None,
+    };
+    match self.kld {
+      // KLDｻﾝﾌﾟﾘﾝｸﾞ
+      Some(k) => {
+        self.active = kld_resample(
+                        &k,
+                        &mut self.wg[..n],
+                        &self.pt[..n],
+                        &mut self.pts,
+                        &mut self.bins,
+                        &mut self.rng,
+                      );
+      }
+      // 再ｻﾝﾌﾟﾘﾝｸﾞ関数
+      None => {
+        (self.resample_fn)(
+           &mut self.wg[..n],
+           &self.pt[..n],
+           &mut self.pts[..n],
+           &mut self.rng,
+        );
+      }
+    }
     // 粒子入替え
     core::mem::swap(
       &mut self.pt,
       &mut self.pts,
     );
     // 重みの初期化
-    self.wg.iter_mut().for_each(|w|
-      *w = 1.0 / PG as f32
-    );
+    self.weight_reset();
+    self.resample_count += 1;
+    // 正則化(カーネルでずらす)
+    if let (PfVariant::Regularized(s), Some(c))
+           = (self.variant, cov) {
+      self.regularize(s, &c);
+    }
     //
     self
   }
-  // 平均、分散
+  // 正則化:x += h * D * ε
+  fn regularize(&mut self,
+                scale: f32,
+                cov  : &[[f32; PM]; PM])
+  {
+    let n  = self.active;
+    let d  = PM as f32;
+    let a  = (4.0 / (d + 2.0)).powf(1.0 / (d + 4.0));
+    let h  = scale * a *
+             (n as f32).powf(-1.0 / (d + 4.0));
+    let l  = cholesky(cov);
+    let normal = Normal::new(0.0, 1.0)
+                         .unwrap();
+    for p in self.pt[..n].iter_mut() {
+      let e: [f32; PM] = core::array::from_fn(|_|
+                           normal.sample(&mut self.rng)
+                         );
+      for (c, lr) in l.iter().enumerate() {
+        p[c] += h * lr.iter()
+                      .zip(e.iter())
+                      .fold(0.0, |s, (l, e)| s + l * e);
+        // 方向の要素は0〜2πに調整
+        if is_angl::<PD>(c) {
+          p[c] = ad_angl(p[c]);
+        }
+      }
+    }
+  }
+  // 重み付き平均、共分散(全次元)
+  // 方向の次元(PD〜PD+PD-2)は円周統計
+  //   平均:atan2(Σw*sin, Σw*cos)
+  //   偏差:平均との差を-π〜πに調整
+  fn weighted_cov(&self)
+    -> ([f32; PM], [[f32; PM]; PM])
+  {
+    let n = self.active;
+    let mut mean = [0.0; PM];
+    let mut sin  = [0.0; PM];
+    let mut cos  = [0.0; PM];
+    for (pt, wg) in self.pt[..n].iter()
+                       .zip(self.wg[..n].iter()) {
+      for (c, p) in pt.iter().enumerate() {
+        if is_angl::<PD>(c) {
+          sin[c] += p.sin() * wg;
+          cos[c] += p.cos() * wg;
+        } else {
+          mean[c] += p * wg;
+        }
+    }}
+    for (c, m) in mean.iter_mut().enumerate() {
+      if is_angl::<PD>(c) {
+        *m = ad_angl(sin[c].atan2(cos[c]));
+      }
+    }
+    let mut cov = [[0.0; PM]; PM];
+    for (pt, wg) in self.pt[..n].iter()
+                       .zip(self.wg[..n].iter()) {
+      let dv: [f32; PM] = core::array::from_fn(|c|
+                            ad_diff::<PD>(c, pt[c], mean[c])
+                          );
+      for (cr, dr) in cov.iter_mut()
+                         .zip(dv.iter()) {
+        for (c, dc) in cr.iter_mut()
+                         .zip(dv.iter()) {
+          *c += dr * dc * wg;
+    }}}
+    (mean, cov)
+  }
+  // 重みの初期化(有効粒子以外は0)
+  fn weight_reset(&mut self) {
+    let n = self.active;
+    self.wg.iter_mut().enumerate()
+           .for_each(|(r, w)|
+      *w = if r < n {1.0 / n as f32} else {0.0}
+    );
+  }
+  // 有効粒子
+  pub fn particles(&self) -> &[[f32; PM]] {
+    &self.pt[..self.active]
+  }
+  // 平均、共分散(全次元)、MAP粒子
+  // 方向の次元は円周平均
+  // map: trueならMAP粒子(last_map)も返す
+  //      再ｻﾝﾌﾟﾘﾝｸﾞ後は重みが一様になるので
+  //      更新時に記録した粒子を返す
+  //      (更新前はNone)
+  pub fn estimate_full(&self, map: bool)
+    -> Estimate<PM>
+  {
+    let (mean, cov) = self.weighted_cov();
+    let map = if map { self.last_map }
+              else { None };
+    Estimate { mean, cov, map }
+  }
+  // 平均、分散(位置次元)
   pub fn estimate(&self)
     -> ([f32; PD] ,[f32; PD]) 
   {
     let mut mean = [0.0; PD];
     let mut var  = [0.0; PD];
     // 平均
-    for r in 0..self.pt.len() {
-      for c in 0..mean.len() {
-        mean[c] += self.pt[r][c] * self.wg[r];
+    let n = self.active;
+    for (pt, wg) in self.pt[..n].iter()
+                       .zip(self.wg[..n].iter()) {
+      for (m, p) in mean.iter_mut()
+                        .zip(pt.iter()) {
+        *m += p * wg;
     }}
     // 分散
-    for r in 0..self.pt.len() {
-      for c in 0..mean.len() {
-        var[c] += (self.pt[r][c] - mean[c])
-                  .powi(2) * self.wg[r];
+    for (pt, wg) in self.pt[..n].iter()
+                       .zip(self.wg[..n].iter()) {
+      for ((v, m), p) in var.iter_mut()
+                            .zip(mean.iter())
+                            .zip(pt.iter()) {
+        *v += (p - m).powi(2) * wg;
     }}
     
     (mean, var)
@@ -223,6 +600,115 @@ impl
                );
     self
   }
+  // === スナップショット ===
+  // 粒子フィルタの状態を呼出し側のバッファへ
+  // バイナリで保存する。関数は保存しない。
+  //
+  // # 形式(filter_snapshot)
+  //   magic  : "PFSN"
+  //   version: SNAPSHOT_VERSION
+  //   次元   : PM, PC, PG, PD, PN
+  //   payload: pt(PG x PM), wg, R, u, Q, dt (f32)
+  //            有効粒子数(u32), 乱数シード(32)
+  // スナップショット形式のバージョン
+  pub const SNAPSHOT_VERSION: u16 = 2;
+  // ペイロード長
+  pub const SNAPSHOT_PAYLOAD: usize =
+    4 * (PG * PM + PG + PN + PC + PC + 1) +
+    4 + 32;
+  // 全体長(バッファサイズ)
+  pub const SNAPSHOT_LEN: usize =
+    snapshot_len(5, Self::SNAPSHOT_PAYLOAD);
+  // スナップショット保存
+  // 乱数は内部状態を取出せないため、
+  // 乱数から新しいシードを引いて自身を
+  // 再シードし、そのシードを保存する。
+  // (復元後の乱数列は保存後と一致する)
+  // 戻り値:書込んだバイト数
+  pub fn save(&mut self, buf: &mut [u8])
+    -> Result<usize, SnapshotError>
+  {
+    let mut w = SnapWriter::new(
+                  buf,
+                  SNAPSHOT_MAGIC,
+                  Self::SNAPSHOT_VERSION,
+                  [PM, PC, PG, PD, PN],
+                  Self::SNAPSHOT_PAYLOAD,
+                )?;
+    let seed = self.rng.gen::<[u8; 32]>();
+    self.rng = StdRng::from_seed(seed);
+    self.pt.iter()
+           .flat_map(|p| p.iter())
+           .for_each(|v| w.f32(*v));
+    self.wg.iter().for_each(|v| w.f32(*v));
+    self.R .iter().for_each(|v| w.f32(*v));
+    self.u .iter().for_each(|v| w.f32(*v));
+    self.Q .iter().for_each(|v| w.f32(*v));
+    w.f32(self.dt);
+    w.u32(self.active as u32);
+    w.put(&seed);
+    Ok(w.finish())
+  }
+  // スナップショット復元
+  // 全項目を読込んで確認してから書換える
+  // (エラー時は変更しない)
+  // 戻り値:読込んだバイト数
+  pub fn restore(&mut self, buf: &[u8])
+    -> Result<usize, SnapshotError>
+  {
+    let mut rd = SnapReader::new(
+                   buf,
+                   SNAPSHOT_MAGIC,
+                   Self::SNAPSHOT_VERSION,
+                   [PM, PC, PG, PD, PN],
+                   Self::SNAPSHOT_PAYLOAD,
+                 )?;
+    let mut pt = [[0.0f32; PM]; PG];
+    let mut wg = [0.0f32; PG];
+    let mut r  = [0.0f32; PN];
+    let mut u  = [0.0f32; PC];
+    let mut q  = [0.0f32; PC];
+    pt.iter_mut()
+      .flat_map(|p| p.iter_mut())
+      .for_each(|v| *v = rd.f32());
+    wg.iter_mut().for_each(|v| *v = rd.f32());
+    r .iter_mut().for_each(|v| *v = rd.f32());
+    u .iter_mut().for_each(|v| *v = rd.f32());
+    q .iter_mut().for_each(|v| *v = rd.f32());
+    let dt     = rd.f32();
+    let active = rd.u32() as usize;
+    let seed: [u8; 32] = rd.take(32)
+                           .try_into()
+                           .unwrap();
+    // 有効粒子数は1〜PG
+    if active == 0 || active > PG {
+      return Err(SnapshotError::Value);
+    }
+    self.pt = pt;
+    self.wg = wg;
+    self.R  = r;
+    self.u  = u;
+    self.Q  = q;
+    self.dt = dt;
+    self.active = active;
+    self.rng = StdRng::from_seed(seed);
+    self.last_map = None;
+    Ok(rd.finish())
+  }
+}
+// --- Clippy対応 ---
+impl
+  <const PM : usize,
+   const PC : usize,
+   const PG : usize,
+   const PD : usize,
+   const PN : usize,
+  >
+  Default for ParticleFilter<PM, PC, PG, PD, PN>
+{
+  fn default() -> Self {
+    Self::new()
+  }
 }
 // =========================================
 // === 関数定義型:粒子生成 ===
@@ -302,12 +788,12 @@ impl
                          .unwrap();
     let mut randn = ||normal.sample(&mut rng);
     
-    for r in 0..pt.len() {
+    for p in pt.iter_mut() {
       for c in (0..PM).rev() {
-        pt[r][c] += randn() * q[0];
+        p[c] += randn() * q[0];
         // 方向の要素は0〜2πに調整
         if c >= PD && c < PD+PD-1 {
-          pt[r][c] = ad_angl(pt[r][c]);
+          p[c] = ad_angl(p[c]);
         }
     }}
 // 制御入力/ﾉｲｽﾞがある場合は、使用する側で
@@ -364,7 +850,7 @@ impl
   {
     // cumsum
     (1..wg.len()).for_each(|r| 
-      wg[r] = wg[r] + wg[r-1]
+      wg[r] += wg[r-1]
     );
     wg[wg.len()-1] = 1.0;
 
@@ -400,7 +886,7 @@ impl
       sum += wg[r];
     });
     // 正規化とcumsumをいっぺんに行う
-    wg[0] = wg[0] / sum;
+    wg[0] /= sum;
     (1..wg.len()).for_each(|r| 
       wg[r] = wg[r] / sum + wg[r-1]
     );
@@ -429,12 +915,14 @@ impl
     );
     // cumsum
     (1..wg.len()).for_each(|r| 
-      wg[r] = wg[r] + wg[r-1]
+      wg[r] += wg[r-1]
     );
 
     let mut j = 0;
     pos.for_each(|(i, p)| {
-      while !(p < wg[j]) && j < pg-1 {j += 1}
+      while p.partial_cmp(&wg[j]) !=
+              Some(Ordering::Less) &&
+            j < pg-1 {j += 1}
       pts[i] = pt[j];
     });
   }
@@ -456,17 +944,157 @@ impl
     );
     // cumsum
     (1..wg.len()).for_each(|r| 
-      wg[r] = wg[r] + wg[r-1]
+      wg[r] += wg[r-1]
     );
 
     let mut j = 0;
     pos.for_each(|(i, p)| {
-      while !(p < wg[j]) && j < pg-1 {j += 1}
+      while p.partial_cmp(&wg[j]) !=
+              Some(Ordering::Less) &&
+            j < pg-1 {j += 1}
       pts[i] = pt[j];
     });
   }
+  // 粒子の対数尤度 Σlog_pdf(hx(pt), R, z)
+  fn log_lh
+  <const PM : usize,
+   const PD : usize,
+   const PN : usize,
+  >
+  (
+    hx: HxFn<PM, PD, PN>,
+    r : &[f32; PN],
+    pt: &[f32; PM],
+    lm: &[[f32; PD]],
+    z : &[[f32; PN]],
+  ) -> f32
+  {
+    let mut l = 0.0;
+    for (lm, z) in lm.iter().zip(z.iter()) {
+      // 粒子を観測値形式に変換
+      let h = hx(*pt, *lm, PD);
+      for ((h, r), z) in h.iter()
+                          .zip(r.iter())
+                          .zip(z.iter()) {
+        l += log_pdf(*h, *r, *z);
+    }}
+    l
+  }
+  // コレスキー分解 A = L @ L.T (下三角L)
+  // 正定値でなければ対角の√で代用
+  fn cholesky<const PM: usize>(
+    a: &[[f32; PM]; PM],
+  ) -> [[f32; PM]; PM]
+  {
+    let mut l = [[0.0f32; PM]; PM];
+    for i in 0..PM {
+      for j in 0..=i {
+        let s = a[i][j] -
+                (0..j).fold(0.0, |s, k|
+                  s + l[i][k] * l[j][k]
+                );
+        if i == j {
+          if s <= 0.0 || !s.is_finite() {
+            // 対角の√で代用
+            let mut d = [[0.0f32; PM]; PM];
+            for (k, dr) in d.iter_mut().enumerate() {
+              dr[k] = a[k][k].max(0.0).sqrt();
+            }
+            return d;
+          }
+          l[i][j] = s.sqrt();
+        } else {
+          l[i][j] = s / l[j][j];
+        }
+      }
+    }
+    l
+  }
+  // KLDサンプリング(多項再サンプリング)
+  // 重みに従って1個ずつ引き、必要数に
+  // 達したら打ち切る。
+  // 最大pts.len()個。binsはビンの作業領域
+  // (pts.len()以上あること)
+  // 戻り値:生成した粒子数(pts[..n])
+  pub fn kld_resample<const PM: usize>(
+    k   : &KldSampling<PM>,
+    wg  : &mut [f32],
+    pt  : &[[f32; PM]],
+    pts : &mut [[f32; PM]],
+    bins: &mut [u32],
+    rng : &mut StdRng,
+  ) -> usize
+  {
+    let pg = pts.len();
+    assert!(bins.len() >= pg,
+            // binsがpts.len()以上じゃないとダメ
+            "bins.len() >= pts.len(), otherwise.");
+    let n = wg.len();
+    // cumsum
+    (1..n).for_each(|r|
+      wg[r] += wg[r-1]
+    );
+    wg[n-1] = 1.0;
+    // 粒子が入ったビン(オープンアドレス法)
+    bins.fill(0);
+    let mut kb   = 0;   // ビン数
+    let mut need = 0;   // 必要粒子数
+    let n_min = k.n_min.clamp(1, pg);
+    let mut m = 0;
+    while m < pg {
+      let u = rng.gen::<f32>();
+      let j = wg.partition_point(|&x| x < u)
+                .min(n - 1);
+      pts[m] = pt[j];
+      m += 1;
+      // 新しいビンなら必要粒子数を更新
+      if bin_insert(bins, k.bin_hash(&pt[j])) {
+        kb  += 1;
+        need = k.bound(kb);
+      }
+      if m >= n_min && m >= need {
+        break;
+      }
+    }
+    m
+  }
+  // ビン登録(新規ならtrue)
+  fn bin_insert(bins: &mut [u32], h: u32) -> bool {
+    let len = bins.len();
+    let mut i = h as usize % len;
+    for _ in 0..len {
+      if bins[i] == h {
+        return false;
+      }
+      if bins[i] == 0 {
+        bins[i] = h;
+        return true;
+      }
+      i = (i + 1) % len;
+    }
+    false
+  }
 // =========================================
 // === 他関数 ===
+  // 方向の次元か(PD〜PD+PD-2)
+  pub fn is_angl<const PD: usize>(c: usize)
+    -> bool
+  {
+    c >= PD && c < PD+PD-1
+  }
+  // 差(方向の次元は-π〜πに収める)
+  pub fn ad_diff<const PD: usize>(
+    c: usize,
+    a: f32,
+    b: f32,
+  ) -> f32
+  {
+    if is_angl::<PD>(c) {
+      ad_angl(a - b + PI) - PI
+    } else {
+      a - b
+    }
+  }
   // 向き調整:0〜2πに収める
   pub fn ad_angl(mut a: f32) -> f32 {
     a %= 2.0 * PI;
@@ -495,6 +1123,42 @@ impl
     */
      .sqrt()
   }
+  // 対数確率密度関数:正規分布
+  pub fn log_pdf(
+    mean: f32,
+    std : f32,
+    x   : f32,
+  ) -> f32
+  {
+    // -log(√2πσ) - (x-μ)^2/2σ^2
+    -0.5 * (2.0 * PI).ln() - std.ln()
+    -
+    (x - mean).powi(2) / (2.0 * std.powi(2))
+  }
+  // 対数重みを正規化した重みに変換
+  //   w[i] = exp(lw[i]-max) / Σexp(lw[j]-max)
+  // 全て-∞(NaN)なら一様分布
+  pub fn log_normalize(lw: &mut [f32]) {
+    let m = lw.iter()
+              .fold(f32::NEG_INFINITY, |m, x|
+                m.max(*x)
+              );
+    let n = lw.len() as f32;
+    if !m.is_finite() {
+      lw.iter_mut().for_each(|w| *w = 1.0 / n);
+      return;
+    }
+    let mut sum = 0.0;
+    lw.iter_mut().for_each(|w| {
+      *w = if w.is_nan() {
+             0.0
+           } else {
+             (*w - m).exp()
+           };
+      sum += *w;
+    });
+    lw.iter_mut().for_each(|w| *w /= sum);
+  }
   // 確率密度関数:正規分布
   pub fn pdf(
     mean: f32,
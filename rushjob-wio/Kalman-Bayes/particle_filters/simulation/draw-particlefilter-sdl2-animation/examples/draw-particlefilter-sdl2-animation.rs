@@ -112,33 +112,29 @@ fn main() ->
       window.update(&display);
       // 更新
       pf.update(sim.lms(), sim.zs());
-      // 再サンプリング判定
-      if pf.neff() < sim.jval {
-        // 再サンプリング
-        pf.resample();
-
-        if i % sim.dstep == 0 {
-          // 予測粒子消去
-          es.set_shape_color(BOX_COLOR)
-            .mode_fillcircle()
-            .set_shape_diameter(1);
-          pf.pts.iter().for_each(|pts|
-            es.set_data(pts[0], pts[1])
-              .draw(&mut display)
-              .unwrap()
-          );
-          window.update(&display);
-          // 粒子描画
-          es.set_shape_color(PT_COLOR)
-            .mode_fillcircle()
-            .set_shape_diameter(1);
-          pf.pt.iter().for_each(|pt|
-            es.set_data(pt[0], pt[1])
-              .draw(&mut display)
-              .unwrap()
-          );
-          window.update(&display);
-        }
+      // 再サンプリング(方針による)
+      if pf.resample_if_needed() &&
+         i % sim.dstep == 0 {
+        // 予測粒子消去
+        es.set_shape_color(BOX_COLOR)
+          .mode_fillcircle()
+          .set_shape_diameter(1);
+        pf.pts.iter().for_each(|pts|
+          es.set_data(pts[0], pts[1])
+            .draw(&mut display)
+            .unwrap()
+        );
+        window.update(&display);
+        // 粒子描画
+        es.set_shape_color(PT_COLOR)
+          .mode_fillcircle()
+          .set_shape_diameter(1);
+        pf.pt.iter().for_each(|pt|
+          es.set_data(pt[0], pt[1])
+            .draw(&mut display)
+            .unwrap()
+        );
+        window.update(&display);
       }
       // 平均、分散算出
       let (mu, _var) = pf.estimate();
@@ -98,15 +98,8 @@ fn main() -> ! {
   for (i, u) in cmd.enumerate() {
     // シミュレータ位置更新
     sim.set_u(u).move_next();
-    // 予測
-    pf.set_u(u).predict();
-    // 更新
-    pf.update(sim.lms(), sim.zs());
-    // 再サンプリング判定
-    if pf.neff() < sim.jval {
-      // 再サンプリング
-      pf.resample();
-    }
+    // 予測、更新、再サンプリング(方針による)
+    pf.set_u(u).step(sim.lms(), sim.zs());
     // 平均、分散算出
     let (mu, _var) = pf.estimate();
 
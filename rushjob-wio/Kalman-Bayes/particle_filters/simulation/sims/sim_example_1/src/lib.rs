@@ -2,6 +2,7 @@
 
 use rand_distr::{Normal, Distribution};
 use rand::prelude::*;
+#[allow(unused_imports)]
 use micromath::F32Ext;
 use particlefilter::*;
 use heapless::Vec;
@@ -78,14 +79,16 @@ const CT : usize = 18;      // 繰返し数
 // 設定②
 const CT : usize = 8;       // 繰返し数
                             // 粒子生成ﾊﾟﾗﾒｰﾀ
+#[allow(clippy::approx_constant)]
 const CP_PARAM : [[f32; 2]; PM] =
 // 設定①〜④⑥〜⑨
     [[0.,20.], [0.,20.], [0., 6.28]];
 /*
 // 設定⑤
    [[1., 5.], [1., 5.], [PI/4., PI/4.]];
-*/                            // ﾘｻﾝﾌﾟﾘﾝｸﾞ判定数
-const JVAL : f32 = PG as f32 / 2.;
+*/                            // ﾘｻﾝﾌﾟﾘﾝｸﾞ方針
+const RESAMPLE: ResamplePolicy =
+      ResamplePolicy::Neff(0.5);
 // ----------------------------------------
 // ランドマーク作成
 fn make_landmarks()
@@ -105,7 +108,7 @@ pub struct SimConfig {
   pub seed : u8,            // 乱数シード
   pub pos  : [f32; PD],     // 実際位置
   pub param: [[f32; 2]; PM],// 粒子生成ﾊﾟﾗﾒｰﾀ
-  pub jval : f32,           // ﾘｻﾝﾌﾟﾘﾝｸﾞ判定
+  pub policy: ResamplePolicy,// ﾘｻﾝﾌﾟﾘﾝｸﾞ方針
   pub u    : [f32; PC],     // 制御入力
   pub dstep: usize,         // 描画ステップ
                             // ﾗﾝﾄﾞﾏｰｸ
@@ -121,7 +124,7 @@ impl SimConfig {
       seed : SEED,
       pos  : INIT_POS,
       param: CP_PARAM,
-      jval : JVAL,
+      policy: RESAMPLE,
       u    : [0.0; PC],
       dstep: D_STEP,
       lmv  : Vec::<[f32; PD], LMC>::new(),
@@ -140,6 +143,7 @@ impl SimConfig {
     pf.R  = SENSER_STD_ERR;
     pf.Q  = CMD_ERR;
     pf.dt = DT;
+    pf.resample_policy = self.policy;
     // 粒子生成関数
      // 一様分布 設定①〜④⑥〜⑨
     pf.create_fn = uniform_particles
@@ -217,6 +221,12 @@ impl SimConfig {
     self
   }
 }
+// --- Clippy対応 ---
+impl Default for SimConfig {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 // ========================================
 // === 関数定義型:状態遷移関数 ===
 pub fn fx(
@@ -231,14 +241,14 @@ pub fn fx(
   let normal = Normal::new(0.0, 1.0).unwrap();
   let mut randn = || normal.sample(&mut rng);
   // 粒子の移動
-  for r in 0..pt.len() { 
+  for p in pt.iter_mut() {
     // 向きの更新
-    pt[r][2] += u[0] + randn() * q[0];
-    pt[r][2] = ad_angl(pt[r][2]);
+    p[2] += u[0] + randn() * q[0];
+    p[2] = ad_angl(p[2]);
     // 位置の更新
     let dist = u[1] * dt + randn() * q[1];
-    pt[r][0] += pt[r][2].cos() * dist;
-    pt[r][1] += pt[r][2].sin() * dist;                   
+    p[0] += p[2].cos() * dist;
+    p[1] += p[2].sin() * dist;                   
   }
 }
 
@@ -45,21 +45,22 @@ const CT : usize = 200;     // 繰返し数
 const CP_PARAM : [[f32; 2]; PM] =
 //    [[-1.,19.], [-1.,19.], [0., 6.28]];
       [[0., 3.], [0., 3.]];
-                            // ﾘｻﾝﾌﾟﾘﾝｸﾞ判定数
-const JVAL: f32 = PG as f32 / 2.;
+                            // ﾘｻﾝﾌﾟﾘﾝｸﾞ方針
+const RESAMPLE: ResamplePolicy =
+      ResamplePolicy::Neff(0.5);
 // ----------------------------------------
 // 制御入力作成
 fn make_cmd()
   -> impl Iterator<Item=[f32; PC]>
 {
-  (1..=CT).into_iter().map(|u| [u as f32])
+  (1..=CT).map(|u| [u as f32])
 }
 // ----------------------------------------
 pub struct SimConfig {
   pub seed : u8,            // 乱数シード
   pub pos  : [f32; PD],     // 実際位置
   pub param: [[f32; 2]; PM],// 粒子生成ﾊﾟﾗﾒｰﾀ
-  pub jval : f32,           // ﾘｻﾝﾌﾟﾘﾝｸﾞ判定
+  pub policy: ResamplePolicy,// ﾘｻﾝﾌﾟﾘﾝｸﾞ方針
   pub u    : [f32; PC],     // 制御入力
   pub dstep: usize,         // 描画ステップ
                             // ﾗﾝﾄﾞﾏｰｸ
@@ -75,7 +76,7 @@ impl SimConfig {
       seed : SEED,
       pos  : INIT_POS,
       param: CP_PARAM,
-      jval : JVAL,
+      policy: RESAMPLE,
       u    : [0.0; PC],
       dstep: D_STEP,
       lmv  : Vec::<[f32; PD], LMC>::new(),
@@ -94,6 +95,7 @@ impl SimConfig {
     pf.R  = SENSER_STD_ERR;
     pf.Q  = CMD_ERR;
     pf.dt = DT;
+    pf.resample_policy = self.policy;
     // 粒子生成関数
 /*
      // 一様分布
@@ -177,6 +179,12 @@ impl SimConfig {
     self
   }
 }
+// --- Clippy対応 ---
+impl Default for SimConfig {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 // ========================================
 // === 関数定義型:状態遷移関数 ===
   pub fn fx(
@@ -187,12 +195,12 @@ impl SimConfig {
     mut rng: &mut StdRng,    // 乱数
   )
   {
-    for r in 0..pt.len() {
-      pt[r][0]  = u[0];
+    for p in pt.iter_mut() {
+      p[0]  = u[0];
       let normal = Normal::new(
-                     pt[r][1], q[0]
+                     p[1], q[0]
                    ).unwrap();
-      pt[r][1] = normal.sample(&mut rng);
+      p[1] = normal.sample(&mut rng);
     }
   }
 // === 関数定義型:観測関数 ===
@@ -2,6 +2,7 @@
 
 use rand_distr::{Normal, Distribution};
 use rand::prelude::*;
+#[allow(unused_imports)]
 use micromath::F32Ext;
 use particlefilter::*;
 use heapless::Vec;
@@ -40,11 +41,13 @@ const INIT_POS:[f32; PD] = [0.5, 0.5];
 const DT : f32   = 1.0;     // dt
 const CT : usize = 20;      // 繰返し数
                             // 粒子生成ﾊﾟﾗﾒｰﾀ
+#[allow(clippy::approx_constant)]
 const CP_PARAM : [[f32; 2]; PM] =
       [[-1.,10.], [-1.,10.], [0., 6.28]];
 //    [[1., 5.], [1., 5.], [PI/4., PI/4.]];
-                            // ﾘｻﾝﾌﾟﾘﾝｸﾞ判定数
-const JVAL: f32 = PG as f32 / 2.;
+                            // ﾘｻﾝﾌﾟﾘﾝｸﾞ方針
+const RESAMPLE: ResamplePolicy =
+      ResamplePolicy::Neff(0.5);
 // ----------------------------------------
 // 制御入力作成
 fn make_cmd()
@@ -57,7 +60,7 @@ pub struct SimConfig {
   pub seed : u8,            // 乱数シード
   pub pos  : [f32; PD],     // 実際位置
   pub param: [[f32; 2]; PM],// 粒子生成ﾊﾟﾗﾒｰﾀ
-  pub jval : f32,           // ﾘｻﾝﾌﾟﾘﾝｸﾞ判定数
+  pub policy: ResamplePolicy,// ﾘｻﾝﾌﾟﾘﾝｸﾞ方針
   pub u    : [f32; PC],     // 制御入力
   pub dstep: usize,         // 描画ステップ
                             // ﾗﾝﾄﾞﾏｰｸ
@@ -73,7 +76,7 @@ impl SimConfig {
       seed : SEED,
       pos  : INIT_POS,
       param: CP_PARAM,
-      jval : JVAL,
+      policy: RESAMPLE,
       u    : [0.0; PC],
       dstep: D_STEP,
       lmv  : Vec::<[f32; PD], LMC>::new(),
@@ -92,6 +95,7 @@ impl SimConfig {
     pf.R  = SENSER_STD_ERR;
     pf.Q  = CMD_ERR;
     pf.dt = DT;
+    pf.resample_policy = self.policy;
     // 粒子生成関数
     pf.create_fn = uniform_particles
                    ::<PM, PD>;
@@ -151,6 +155,12 @@ impl SimConfig {
     self
   }
 }
+// --- Clippy対応 ---
+impl Default for SimConfig {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 // ========================================
 // === 関数定義型:状態遷移関数 ===
 pub fn fx(
@@ -165,13 +175,13 @@ pub fn fx(
   let normal = Normal::new(0.0, 1.0).unwrap();
   let mut randn = || normal.sample(&mut rng);
   // 粒子の移動
-  for i in 0..pt.len() { 
+  for p in pt.iter_mut() {
     // 向きの更新
-    pt[i][2] += u[0] + randn() * q[0];
-    pt[i][2] = ad_angl(pt[i][2]);
+    p[2] += u[0] + randn() * q[0];
+    p[2] = ad_angl(p[2]);
     // 位置の更新
     let dist = u[1] * dt + randn() * q[1];
-    pt[i][0] += pt[i][2].cos() * dist;
-    pt[i][1] += pt[i][2].sin() * dist;                   
+    p[0] += p[2].cos() * dist;
+    p[1] += p[2].sin() * dist;                   
   }
 }
\ No newline at end of file
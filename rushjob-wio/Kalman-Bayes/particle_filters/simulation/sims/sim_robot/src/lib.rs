@@ -4,6 +4,7 @@ use rand::prelude::*;
 use particlefilter::*;
 use heapless::Vec;
 use core::f32::consts::PI;
+#[allow(unused_imports)]
 use micromath::F32Ext;
 
 pub use robotukfsim::*;     // ﾛﾎｯﾄｼﾐｭﾚｰﾀ
@@ -22,14 +23,15 @@ const SENSER_STD_ERR: [f32; PN] = [0.1, 0.1];
                             // 粒子生成ﾊﾟﾗﾒｰﾀ
 const CP_PARAM : [[f32; 2]; PM] =
       [[2., 5.], [6., 5.], [0., 2.*PI]];
-                            // ﾘｻﾝﾌﾟﾘﾝｸﾞ判定数
-const JVAL: f32 = PG as f32 / 2.;
+                            // ﾘｻﾝﾌﾟﾘﾝｸﾞ方針
+const RESAMPLE: ResamplePolicy =
+      ResamplePolicy::Neff(0.5);
 // ----------------------------------------
 pub struct SimConfig {
   pub seed : u8,            // 乱数シード
   pub pos  : [f32; PD],     // 実際位置
   pub param: [[f32; 2]; PM],// 粒子生成ﾊﾟﾗﾒｰﾀ
-  pub jval : f32,           // ﾘｻﾝﾌﾟﾘﾝｸﾞ判定
+  pub policy: ResamplePolicy,// ﾘｻﾝﾌﾟﾘﾝｸﾞ方針
   pub u    : [f32; PC],     // 制御入力
   pub dstep: usize,         // 描画ステップ
                             // ﾗﾝﾄﾞﾏｰｸ
@@ -51,7 +53,7 @@ impl SimConfig {
       seed : SEED,
       pos  : [rb.sim_pos[0], rb.sim_pos[1]],
       param: CP_PARAM,
-      jval : JVAL,
+      policy: RESAMPLE,
       u    : [0.0; PC],
       dstep: ELLIPSE_STEP,
       lmv  : Vec::<[f32; PD], LMC>::new(),
@@ -72,6 +74,7 @@ impl SimConfig {
     pf.Q  = [self.rb.sigma_bearing.powi(2),
              self.rb.sigma_range.powi(2)];
     pf.dt = DT;
+    pf.resample_policy = self.policy;
     // 粒子生成関数
      // 一様分布
 /*
@@ -120,7 +123,7 @@ impl SimConfig {
     self.zv.clear();
     let mut za = [0.0_f32; PN];
     self.rb.z().chunks(PN).for_each(|z| {
-      (0..PN).into_iter().for_each(|c| 
+      (0..PN).for_each(|c| 
          za[c] = z[c]
       );
       self.zv.push(za).unwrap();
@@ -169,6 +172,12 @@ impl SimConfig {
     self
   }
 }
+// --- Clippy対応 ---
+impl Default for SimConfig {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 // ========================================
 // === 関数定義型:状態遷移関数 ===
 pub fn fx(
@@ -183,19 +192,19 @@ pub fn fx(
   let normal = Normal::new(0.0, 1.0).unwrap();
   let mut randn = || normal.sample(&mut rng);
   // 粒子の移動
-  for r in 0..pt.len() {
-    pt[r] = RobotUkfSim::move_to(
-              &[pt[r][0], pt[r][1], pt[r][2]],
-              dt, 
-              u
-            );
+  for p in pt.iter_mut() {
+    *p = RobotUkfSim::move_to(
+           &[p[0], p[1], p[2]],
+           dt, 
+           u
+         );
     // 向きにノイズ追加
-    pt[r][2] += randn() * q[0];
-    pt[r][2]  = ad_angl(pt[r][2]);
+    p[2] += randn() * q[0];
+    p[2]  = ad_angl(p[2]);
     // 位置にノイズ追加
     let dist = randn() * q[1];
-    pt[r][0] += pt[r][2].cos() * dist;
-    pt[r][1] += pt[r][2].sin() * dist;
+    p[0] += p[2].cos() * dist;
+    p[1] += p[2].sin() * dist;
   }
 }
 // === 関数定義型:観測関数ﾃﾞﾌｫﾙﾄ実装 ===
@@ -214,7 +223,7 @@ pub fn fx(
             );
     // 向きの要素がある場合ﾗﾝﾄﾞﾏｰｸとの相対角度
     if PM > PD {
-      (PD..2*PD-1).into_iter().enumerate()
+      (PD..2*PD-1).enumerate()
       .for_each(|(i, c)|
         // ランドマークとの相対角度
         hx[i+1] = RobotUkfSim
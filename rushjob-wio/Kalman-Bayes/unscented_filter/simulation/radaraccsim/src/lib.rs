@@ -1,7 +1,8 @@
 #![no_std]
 
-use rand_distr::{Normal, Distribution};
+use rand_distr::{Normal, Poisson, Distribution};
 use rand::prelude::*;
+#[allow(unused_imports)]
 use micromath::F32Ext;
 
 const N:usize = 2;
@@ -155,3 +156,112 @@ impl RadarStation {
     self
   }
 }
+
+// クラッタ付きレーダー ======================
+// 航空機を確率pdで検出し、それ以外に
+// 平均clutter_mean個(ポアソン分布)の
+// 偽観測(クラッタ)を観測範囲に一様に
+// ばらまいた観測値リストを返す。
+//   観測範囲 直距離: 0 .. max_range
+//            仰角  : angle_min .. angle_max
+// JPDA等のクラッタ密度は density() で取得。
+pub struct ClutterRadarStation {
+  pub radar       : RadarStation,
+  pub pd          : f32, // 検出確率
+  pub clutter_mean: f32, // 平均クラッタ数
+  pub max_range   : f32, // 最大直距離
+  pub angle_min   : f32, // 仰角下限
+  pub angle_max   : f32, // 仰角上限
+  rng             : StdRng,
+}
+impl ClutterRadarStation {
+  pub fn new() -> Self {
+    ClutterRadarStation {
+      radar       : RadarStation::new(),
+      pd          : 0.9,
+      clutter_mean: 1.0,
+      max_range   : 10000.,
+      angle_min   : 0.,
+      angle_max   : core::f32::consts::FRAC_PI_2,
+      rng         : StdRng::from_seed(
+                      [43; 32]
+                    ),
+    }
+  }
+  // クラッタ密度(1/(m・rad))
+  pub fn density(&self) -> f32 {
+    self.clutter_mean /
+    (self.max_range *
+     (self.angle_max - self.angle_min))
+  }
+  // 1スキャン分の観測値
+  // 戻り値:(観測値, 件数, 航空機の観測位置)
+  //   観測値はK個を超えた分を捨てる
+  pub fn scan<const K: usize>(
+    &mut self,
+    ac_pos: [f32; N],
+  ) -> ([[f32; N]; K], usize, Option<usize>)
+  {
+    let mut zs = [[0.; N]; K];
+    let mut count = 0;
+    // クラッタ数
+    let nc = if self.clutter_mean > 0. {
+      Poisson::new(self.clutter_mean)
+        .unwrap()
+        .sample(&mut self.rng) as usize
+    } else {
+      0
+    };
+    for z in zs.iter_mut().take(nc) {
+      *z = [
+        self.rng.gen::<f32>() * self.max_range,
+        self.angle_min +
+        self.rng.gen::<f32>() *
+        (self.angle_max - self.angle_min),
+      ];
+      count += 1;
+    }
+    // 航空機の観測値をランダムな位置に挿入
+    let mut target = None;
+    if self.rng.gen::<f32>() < self.pd {
+      let z = self.radar.noisy_reading(ac_pos);
+      let k = self.rng.gen_range(0..=count)
+                  .min(K - 1);
+      if count < K {
+        zs[count] = zs[k];
+        count += 1;
+      }
+      zs[k]  = z;
+      target = Some(k);
+    }
+    (zs, count, target)
+  }
+// セッター
+  // 乱数シード
+  pub fn set_random_seed(
+    &mut self,
+    random_seed: u8
+  ) -> &mut Self {
+    self.radar.set_random_seed(random_seed);
+    self.rng = StdRng::from_seed(
+                [random_seed.wrapping_add(1); 32]
+               );
+    self
+  }
+}
+// --- Clippy対応 ---
+impl Default for ACSim {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl Default for RadarStation {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl Default for ClutterRadarStation {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,203 @@
+#![no_std]
+// スナップショット(保存/復元)共通部
+//
+// フィルタの状態を呼出し側のバッファへ
+// バイナリで保存するための書込み/読込みと
+// CRC-32。形式はフィルタ毎のmagic, version,
+// 次元(D個)で区別する。
+//
+// # 形式(リトルエンディアン)
+//   magic  : 4
+//   version: 2
+//   次元   : 2xD
+//   len    : 4  ペイロード長
+//   payload: len
+//   crc    : 4  CRC-32(magic〜payload)
+//
+//  SnapReader::newでヘッダ、長さ、CRCを
+//  確認する。復元側はペイロードを全て
+//  読込んで値を確認してから書換えること
+//  (エラー時は変更しない)。
+// -----------------------------------------
+
+// スナップショットエラー
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+  // バッファが足りない
+  BufferTooSmall,
+  // magicが一致しない
+  BadMagic,
+  // 未対応のバージョン
+  Version,
+  // 次元が一致しない
+  Dimension,
+  // ペイロード長が一致しない
+  Length,
+  // 値が範囲外
+  Value,
+  // CRCが一致しない
+  Checksum,
+}
+// ヘッダ長(次元数d)
+pub const fn header_len(d: usize) -> usize {
+  4 + 2 + 2 * d + 4
+}
+// 全体長(次元数d、ペイロード長)
+//   バッファサイズに使う
+pub const fn snapshot_len(d      : usize,
+                          payload: usize)
+  -> usize
+{
+  header_len(d) + payload + 4
+}
+// スナップショット書込み
+// (newでヘッダを書き、finishでCRCを付ける)
+pub struct SnapWriter<'a> {
+  buf: &'a mut [u8],
+  pos: usize,
+}
+impl<'a> SnapWriter<'a> {
+  pub fn new<const D: usize>(
+    buf    : &'a mut [u8],
+    magic  : &[u8; 4],
+    version: u16,
+    dim    : [usize; D],
+    payload: usize,
+  ) -> Result<Self, SnapshotError>
+  {
+    if buf.len() < snapshot_len(D, payload) {
+      return Err(SnapshotError::BufferTooSmall);
+    }
+    let mut w = Self { buf, pos: 0 };
+    w.put(magic);
+    w.put(&version.to_le_bytes());
+    dim.iter().for_each(|d|
+      w.put(&(*d as u16).to_le_bytes())
+    );
+    w.u32(payload as u32);
+    Ok(w)
+  }
+  pub fn put(&mut self, b: &[u8]) {
+    self.buf[self.pos..self.pos + b.len()]
+        .copy_from_slice(b);
+    self.pos += b.len();
+  }
+  pub fn u8(&mut self, v: u8) {
+    self.put(&[v]);
+  }
+  pub fn u32(&mut self, v: u32) {
+    self.put(&v.to_le_bytes());
+  }
+  pub fn f32(&mut self, v: f32) {
+    self.put(&v.to_le_bytes());
+  }
+  // Option: 有無1 + 値4
+  pub fn opt_f32(&mut self, v: Option<f32>) {
+    self.u8(v.is_some() as u8);
+    self.f32(v.unwrap_or(0.0));
+  }
+  pub fn opt_u32(&mut self, v: Option<u32>) {
+    self.u8(v.is_some() as u8);
+    self.u32(v.unwrap_or(0));
+  }
+  // CRCを付けて書込んだバイト数を返す
+  pub fn finish(mut self) -> usize {
+    let c = crc32(&self.buf[..self.pos]);
+    self.u32(c);
+    self.pos
+  }
+}
+// スナップショット読込み
+// (newでヘッダ、長さ、CRCを確認する)
+pub struct SnapReader<'a> {
+  buf: &'a [u8],
+  pos: usize,
+}
+impl<'a> SnapReader<'a> {
+  pub fn new<const D: usize>(
+    buf    : &'a [u8],
+    magic  : &[u8; 4],
+    version: u16,
+    dim    : [usize; D],
+    payload: usize,
+  ) -> Result<Self, SnapshotError>
+  {
+    if buf.len() < header_len(D) {
+      return Err(SnapshotError::BufferTooSmall);
+    }
+    if &buf[0..4] != magic {
+      return Err(SnapshotError::BadMagic);
+    }
+    let mut r = Self { buf, pos: 4 };
+    if r.u16() != version {
+      return Err(SnapshotError::Version);
+    }
+    if dim.iter().any(|d| r.u16() as usize != *d) {
+      return Err(SnapshotError::Dimension);
+    }
+    if r.u32() as usize != payload {
+      return Err(SnapshotError::Length);
+    }
+    let end = header_len(D) + payload;
+    if buf.len() < end + 4 {
+      return Err(SnapshotError::BufferTooSmall);
+    }
+    let c = u32::from_le_bytes(
+              buf[end..end + 4].try_into().unwrap()
+            );
+    if crc32(&buf[..end]) != c {
+      return Err(SnapshotError::Checksum);
+    }
+    Ok(r)
+  }
+  pub fn take(&mut self, n: usize) -> &'a [u8] {
+    let b = &self.buf[self.pos..self.pos + n];
+    self.pos += n;
+    b
+  }
+  pub fn u8(&mut self) -> u8 {
+    self.take(1)[0]
+  }
+  pub fn u16(&mut self) -> u16 {
+    u16::from_le_bytes(
+      self.take(2).try_into().unwrap()
+    )
+  }
+  pub fn u32(&mut self) -> u32 {
+    u32::from_le_bytes(
+      self.take(4).try_into().unwrap()
+    )
+  }
+  pub fn f32(&mut self) -> f32 {
+    f32::from_le_bytes(
+      self.take(4).try_into().unwrap()
+    )
+  }
+  pub fn opt_f32(&mut self) -> Option<f32> {
+    let s = self.u8() != 0;
+    let v = self.f32();
+    s.then_some(v)
+  }
+  pub fn opt_u32(&mut self) -> Option<u32> {
+    let s = self.u8() != 0;
+    let v = self.u32();
+    s.then_some(v)
+  }
+  // CRC分を含めた読込みバイト数
+  pub fn finish(self) -> usize {
+    self.pos + 4
+  }
+}
+// CRC-32(IEEE 802.3, 反転多項式0xEDB88320)
+// テーブルなしのビット毎計算
+pub fn crc32(data: &[u8]) -> u32 {
+  !data.iter().fold(0xFFFF_FFFFu32, |c, b| {
+    (0..8).fold(c ^ *b as u32, |c, _|
+      if c & 1 != 0 {
+        (c >> 1) ^ 0xEDB8_8320
+      } else {
+        c >> 1
+      }
+    )
+  })
+}